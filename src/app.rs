@@ -1,40 +1,176 @@
 use crate::config::Config;
-use crate::grouping::{Group, group_by_downloads, group_by_media};
+use crate::filters::is_partial;
+use crate::grouping::{
+    Group, GroupKind, ReleaseCache, group_by_downloads, group_by_media, link_related,
+};
 use crate::qbittorrent::TorrentInfo;
-use crate::scanner::FileNode;
-use std::collections::HashMap;
-
-static EMPTY_GROUPS: Vec<Group> = Vec::new();
+use crate::scanner::{
+    CancelToken, FileKey, FileNode, NodeKind, RootInfo, ScanIssue, ScanProgress, ScanReport,
+};
+use crate::snapshot::{self, Snapshot, SnapshotDiff, SnapshotInfo};
+use crate::watcher::{FileChange, WatchedFile};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
 
 pub struct App {
     pub config: Config,
     pub running: bool,
     pub active_tab: Tab,
     pub media_groups: Option<Vec<Group>>, // For Tab::Media (Aggregated)
-    pub folder_groups: HashMap<usize, Vec<Group>>, // For Tab::MediaFolder(i)
-    pub download_groups: Option<Vec<Group>>, // For Tab::Downloads
+    pub folder_groups: HashMap<usize, Vec<usize>>, // For Tab::MediaFolder(i), indices into media_groups
+    pub download_groups: Option<Vec<Group>>,       // For Tab::Downloads (Aggregated)
+    pub download_folder_groups: HashMap<usize, Vec<usize>>, // For Tab::DownloadFolder(i), indices into download_groups
     pub nodes: Vec<FileNode>,
+    /// Parsed names of the group containers; forget the paths that change.
+    releases: ReleaseCache,
+    /// Index into [`App::rows`].
     pub selected_index: usize,
+    /// Groups and folders shown with their contents, by path. Changes must
+    /// go through [`App::invalidate_rows`].
+    expanded: HashSet<PathBuf>,
+    /// Bumped whenever the groups or `expanded` change.
+    rows_generation: u64,
+    /// Rows built by [`App::rows`] and the view they were built for.
+    row_cache: RefCell<Option<(RowView, Vec<CachedRow>)>>,
+    /// Container of the group the last jump to a related group left.
+    jump_origin: Option<PathBuf>,
     pub show_details: bool,
+    pub show_warnings: bool,
+    pub warning_index: usize,
     pub show_confirmation: bool,
     pub delete_scope: DeleteScope,
     pub available_scopes: Vec<DeleteScope>,
+    /// Whether the pending delete also takes files still being downloaded.
+    pub include_incomplete: bool,
+    pub show_relink: bool,
+    pub relink_target: RelinkTarget,
+    pub status_message: Option<String>,
+    pub changes: Option<ChangesView>,
     pub search_query: String,
     pub search_active: bool,
     pub sort_by: SortBy,
     pub sort_order: SortOrder,
     pub filter: FilterMode,
+    pub kind_filter: KindFilter,
     pub pending_qbit_deletions: Vec<String>,
     pub state: AppState,
     pub torrents: Vec<TorrentInfo>,
+    /// Folder the torrent client keeps unfinished downloads in.
+    pub incomplete_dir: Option<PathBuf>,
+    pub last_scan: Option<ScanReport>,
+    pub watch: WatchStatus,
+    /// Where the nodes come from when they weren't scanned on this machine.
+    /// Their files can't be touched from here, so deleting and relinking are
+    /// disabled.
+    pub remote_source: Option<String>,
+    /// Watcher changes held back while a scan or a confirmation is running.
+    pending_changes: Vec<FileChange>,
+}
+
+/// A line of the list: a group, or a folder or file inside an expanded one.
+pub struct Row<'a> {
+    pub group: &'a Group,
+    /// The group's container, or the folder or file below it this row is for.
+    pub path: Rc<Path>,
+    /// 0 for the group itself.
+    pub depth: usize,
+    /// Indices into [`App::nodes`] of the files at or below `path`.
+    pub nodes: Rc<[usize]>,
+    /// Whether the row holds other rows, shown when it is expanded.
+    pub is_dir: bool,
+    pub expanded: bool,
+}
+
+/// A [`Row`] as kept by [`App::rows`], with its group as an index into
+/// [`App::tab_groups`].
+struct CachedRow {
+    group: usize,
+    path: Rc<Path>,
+    depth: usize,
+    nodes: Rc<[usize]>,
+    is_dir: bool,
+    expanded: bool,
+}
+
+impl CachedRow {
+    fn borrow<'a>(&self, groups: &'a [Group]) -> Row<'a> {
+        Row {
+            group: &groups[self.group],
+            path: self.path.clone(),
+            depth: self.depth,
+            nodes: self.nodes.clone(),
+            is_dir: self.is_dir,
+            expanded: self.expanded,
+        }
+    }
+}
+
+/// Everything the row list depends on, to tell when the cached one is stale.
+#[derive(PartialEq)]
+struct RowView {
+    /// [`App::rows_generation`] when the rows were built.
+    generation: u64,
+    tab: Tab,
+    search: String,
+    filter: FilterMode,
+    kind: KindFilter,
+    sort_by: SortBy,
+    sort_order: SortOrder,
+}
+
+impl Row<'_> {
+    pub fn files<'n>(&'n self, nodes: &'n [FileNode]) -> impl Iterator<Item = &'n FileNode> + 'n {
+        self.nodes.iter().map(move |&i| &nodes[i])
+    }
+}
+
+/// The latest snapshot compared with an older one.
+pub struct ChangesView {
+    /// Saved snapshots, oldest first.
+    pub snapshots: Vec<SnapshotInfo>,
+    /// Index of the older snapshot being compared against the latest.
+    pub base: usize,
+    pub diff: Result<SnapshotDiff, String>,
+    pub scroll: usize,
+}
+
+impl ChangesView {
+    fn compare(&mut self) {
+        let Some(latest) = self.snapshots.last() else {
+            return;
+        };
+        let load = |info: &SnapshotInfo| {
+            Snapshot::load(&info.path).map_err(|e| format!("{}: {:#}", info.path.display(), e))
+        };
+        self.diff = load(&self.snapshots[self.base])
+            .and_then(|old| Ok(snapshot::diff(&old, &load(latest)?)));
+        self.scroll = 0;
+    }
+}
+
+/// How the node list is kept current between manual rescans.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum WatchStatus {
+    Off,
+    Live,
+    /// Watching isn't possible, so the roots are rescanned on a timer.
+    Polling {
+        every: Duration,
+        reason: String,
+    },
 }
 
 #[derive(Debug)]
 pub enum AppState {
     Ready,
     Scanning {
-        processed: usize,
+        progress: ScanProgress,
+        started: Instant,
         receiver: std::sync::mpsc::Receiver<crate::scanner::ScanEvent>,
+        cancel: CancelToken,
     },
     Error(String),
 }
@@ -44,6 +180,9 @@ pub enum FilterMode {
     All,
     Orphans,
     Hardlinked,
+    Duplicates,
+    ExternalLinks,
+    Incomplete,
 }
 
 impl FilterMode {
@@ -51,7 +190,39 @@ impl FilterMode {
         match self {
             FilterMode::All => FilterMode::Orphans,
             FilterMode::Orphans => FilterMode::Hardlinked,
-            FilterMode::Hardlinked => FilterMode::All,
+            FilterMode::Hardlinked => FilterMode::Duplicates,
+            FilterMode::Duplicates => FilterMode::ExternalLinks,
+            FilterMode::ExternalLinks => FilterMode::Incomplete,
+            FilterMode::Incomplete => FilterMode::All,
+        }
+    }
+}
+
+/// Which kinds of groups are listed, on top of [`FilterMode`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KindFilter {
+    All,
+    Movies,
+    Series,
+    Other,
+}
+
+impl KindFilter {
+    pub fn next(self) -> Self {
+        match self {
+            KindFilter::All => KindFilter::Movies,
+            KindFilter::Movies => KindFilter::Series,
+            KindFilter::Series => KindFilter::Other,
+            KindFilter::Other => KindFilter::All,
+        }
+    }
+
+    fn matches(self, kind: GroupKind) -> bool {
+        match self {
+            KindFilter::All => true,
+            KindFilter::Movies => kind == GroupKind::Movie,
+            KindFilter::Series => kind == GroupKind::Series,
+            KindFilter::Other => kind == GroupKind::Other,
         }
     }
 }
@@ -61,6 +232,8 @@ pub enum SortBy {
     Name,
     Size,
     DateAdded,
+    Year,
+    Quality,
 }
 
 impl SortBy {
@@ -68,7 +241,9 @@ impl SortBy {
         match self {
             SortBy::Name => SortBy::Size,
             SortBy::Size => SortBy::DateAdded,
-            SortBy::DateAdded => SortBy::Name,
+            SortBy::DateAdded => SortBy::Year,
+            SortBy::Year => SortBy::Quality,
+            SortBy::Quality => SortBy::Name,
         }
     }
 }
@@ -116,11 +291,28 @@ impl DeleteScope {
     }
 }
 
+/// Which side of a duplicate pair gets replaced by a hardlink to the other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RelinkTarget {
+    Media,
+    Downloads,
+}
+
+impl RelinkTarget {
+    pub fn toggle(self) -> Self {
+        match self {
+            RelinkTarget::Media => RelinkTarget::Downloads,
+            RelinkTarget::Downloads => RelinkTarget::Media,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Tab {
     Media,
     MediaFolder(usize),
     Downloads,
+    DownloadFolder(usize),
 }
 
 impl App {
@@ -131,134 +323,608 @@ impl App {
             active_tab: Tab::Media,
             media_groups: None,
             folder_groups: HashMap::new(),
+            releases: ReleaseCache::default(),
+            rows_generation: 0,
+            row_cache: RefCell::new(None),
             download_groups: None,
+            download_folder_groups: HashMap::new(),
             nodes,
             selected_index: 0,
+            expanded: HashSet::new(),
+            jump_origin: None,
             show_details: false,
+            show_warnings: false,
+            warning_index: 0,
             show_confirmation: false,
             delete_scope: DeleteScope::Downloads,
             available_scopes: Vec::new(),
+            include_incomplete: false,
+            show_relink: false,
+            relink_target: RelinkTarget::Media,
+            status_message: None,
+            changes: None,
             search_query: String::new(),
             search_active: false,
             sort_by: SortBy::Name,
             sort_order: SortOrder::Ascending,
             filter: FilterMode::All,
+            kind_filter: KindFilter::All,
             pending_qbit_deletions: Vec::new(),
             state: AppState::Ready,
             torrents: _torrents,
+            incomplete_dir: None,
+            last_scan: None,
+            watch: WatchStatus::Off,
+            remote_source: None,
+            pending_changes: Vec::new(),
         };
         app.refresh_groups();
         app
     }
 
     pub fn refresh_groups(&mut self) {
+        self.invalidate_rows();
         self.media_groups = None;
         self.folder_groups.clear();
         self.download_groups = None;
+        self.download_folder_groups.clear();
     }
 
     pub fn toggle_details(&mut self) {
         self.show_details = !self.show_details;
     }
 
-    pub fn ensure_groups(&mut self) {
-        match self.active_tab {
-            Tab::Media => {
-                if self.media_groups.is_none() {
-                    self.media_groups = Some(group_by_media(&self.nodes, &self.config.media_dirs));
+    pub fn scan_issues(&self) -> &[ScanIssue] {
+        self.last_scan
+            .as_ref()
+            .map(|report| report.issues.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// Why files in the active tab may be unable to hardlink: roots on a
+    /// different device than the downloads, and filesystems mounted inside
+    /// the roots shown.
+    pub fn filesystem_warnings(&self) -> Vec<String> {
+        let Some(report) = &self.last_scan else {
+            return Vec::new();
+        };
+        let (downloads, media): (Vec<&RootInfo>, Vec<&RootInfo>) =
+            report.roots.iter().partition(|r| r.is_download);
+        let download_devs: Vec<u64> = downloads.iter().filter_map(|r| r.dev).collect();
+        if download_devs.is_empty() {
+            return Vec::new();
+        }
+        fn find<'a>(roots: &[&'a RootInfo], dir: Option<&PathBuf>) -> Vec<&'a RootInfo> {
+            dir.and_then(|dir| roots.iter().find(|r| r.path == *dir).copied())
+                .into_iter()
+                .collect()
+        }
+        let shown: Vec<&RootInfo> = match self.active_tab {
+            Tab::Media => media.clone(),
+            Tab::MediaFolder(idx) => find(&media, self.config.media_dirs.get(idx)),
+            Tab::Downloads => downloads.clone(),
+            Tab::DownloadFolder(idx) => find(
+                &downloads,
+                self.config.download_dirs.get(idx).map(|d| &d.path),
+            ),
+        };
+
+        let mut warnings = Vec::new();
+        // Media can be hardlinked as long as some download root shares its
+        // filesystem
+        let foreign = |root: &RootInfo| root.dev.is_some_and(|dev| !download_devs.contains(&dev));
+        if matches!(self.active_tab, Tab::Downloads | Tab::DownloadFolder(_)) {
+            for download in &shown {
+                let Some(download_dev) = download.dev else {
+                    continue;
+                };
+                for root in media
+                    .iter()
+                    .filter(|r| r.dev.is_some_and(|d| d != download_dev))
+                {
+                    warnings.push(if downloads.len() > 1 {
+                        format!(
+                            "{} is on another filesystem than {}: its downloads can only be copied there, never hardlinked",
+                            root.path.display(),
+                            download.path.display()
+                        )
+                    } else {
+                        format!(
+                            "{} is on another filesystem: downloads can only be copied there, never hardlinked",
+                            root.path.display()
+                        )
+                    });
                 }
             }
+        } else {
+            for root in shown.iter().filter(|r| foreign(r)) {
+                warnings.push(format!(
+                    "{} is on another filesystem than the downloads: its files can never be hardlinked",
+                    root.path.display()
+                ));
+            }
+        }
+        for root in &shown {
+            for mount in root
+                .mounts
+                .iter()
+                .filter(|m| !download_devs.contains(&m.dev))
+            {
+                warnings.push(format!(
+                    "{} is a separate filesystem mounted inside {}{}",
+                    mount.path.display(),
+                    root.path.display(),
+                    if self.config.one_file_system {
+                        " (not scanned)"
+                    } else {
+                        ""
+                    }
+                ));
+            }
+        }
+        warnings
+    }
+
+    /// Opens the changes view on the two latest snapshots, or closes it.
+    pub fn toggle_changes(&mut self) {
+        if self.changes.take().is_some() {
+            return;
+        }
+        let snapshots = snapshot::list();
+        if snapshots.len() < 2 {
+            self.status_message = Some(format!(
+                "Need two snapshots to compare, {} saved so far",
+                snapshots.len()
+            ));
+            return;
+        }
+        let mut view = ChangesView {
+            base: snapshots.len() - 2,
+            snapshots,
+            diff: Ok(SnapshotDiff::default()),
+            scroll: 0,
+        };
+        view.compare();
+        self.changes = Some(view);
+    }
+
+    /// Moves the base of the comparison to an older (`true`) or newer
+    /// snapshot.
+    pub fn shift_changes_base(&mut self, older: bool) {
+        let Some(view) = &mut self.changes else {
+            return;
+        };
+        let base = if older {
+            view.base.checked_sub(1)
+        } else {
+            Some(view.base + 1).filter(|&b| b + 1 < view.snapshots.len())
+        };
+        if let Some(base) = base {
+            view.base = base;
+            view.compare();
+        }
+    }
+
+    pub fn toggle_warnings(&mut self) {
+        self.show_warnings = !self.show_warnings && !self.scan_issues().is_empty();
+        self.warning_index = 0;
+    }
+
+    pub fn select_next_warning(&mut self) {
+        let len = self.scan_issues().len();
+        if len > 0 {
+            self.warning_index = (self.warning_index + 1) % len;
+        }
+    }
+
+    pub fn select_prev_warning(&mut self) {
+        let len = self.scan_issues().len();
+        if len > 0 {
+            self.warning_index = (self.warning_index + len - 1) % len;
+        }
+    }
+
+    pub fn ensure_groups(&mut self) {
+        // Both views are built together so each group knows its related
+        // groups in the other one
+        if self.media_groups.is_none() || self.download_groups.is_none() {
+            let mut media = group_by_media(
+                &self.nodes,
+                &mut self.releases,
+                &self.config.media_dirs,
+                &self.config.media_kinds,
+                &self.config.grouping,
+            );
+            let mut downloads = group_by_downloads(
+                &self.nodes,
+                &mut self.releases,
+                &self.config.download_paths(),
+                &self.config.grouping,
+            );
+            link_related(&self.nodes, &mut media, &mut downloads);
+            self.media_groups = Some(media);
+            self.download_groups = Some(downloads);
+            self.invalidate_rows();
+        }
+        match self.active_tab {
+            Tab::Media | Tab::Downloads => {}
             Tab::MediaFolder(idx) => {
+                // A folder's groups are the aggregated groups found inside it
                 if !self.folder_groups.contains_key(&idx) {
-                    if let Some(dir) = self.config.media_dirs.get(idx) {
-                        let groups = group_by_media(&self.nodes, &[dir.clone()]);
-                        self.folder_groups.insert(idx, groups);
-                    }
+                    let dirs = &self.config.media_dirs;
+                    let groups = self
+                        .media_groups
+                        .iter()
+                        .flatten()
+                        .enumerate()
+                        .filter(|(_, g)| {
+                            g.media_container
+                                .as_deref()
+                                .and_then(|c| dirs.iter().position(|d| c.starts_with(d)))
+                                == Some(idx)
+                        })
+                        .map(|(i, _)| i)
+                        .collect();
+                    self.folder_groups.insert(idx, groups);
+                    self.invalidate_rows();
                 }
             }
-            Tab::Downloads => {
-                if self.download_groups.is_none() {
-                    if let Some(ref download_dir) = self.config.download_dir {
-                        self.download_groups = Some(group_by_downloads(&self.nodes, download_dir));
-                    }
+            Tab::DownloadFolder(idx) => {
+                if !self.download_folder_groups.contains_key(&idx) {
+                    let dirs = &self.config.download_dirs;
+                    let groups = self
+                        .download_groups
+                        .iter()
+                        .flatten()
+                        .enumerate()
+                        .filter(|(_, g)| {
+                            g.downloads_container
+                                .as_deref()
+                                .and_then(|c| dirs.iter().position(|d| c.starts_with(&d.path)))
+                                == Some(idx)
+                        })
+                        .map(|(i, _)| i)
+                        .collect();
+                    self.download_folder_groups.insert(idx, groups);
+                    self.invalidate_rows();
                 }
             }
         }
     }
 
-    pub fn current_groups(&self) -> Vec<&Group> {
+    /// The groups in the other view that share a file with `group`.
+    pub fn related_groups(&self, group: &Group) -> Vec<&Group> {
+        let other = if group.media_container.is_some() {
+            &self.download_groups
+        } else {
+            &self.media_groups
+        };
+        match other {
+            Some(all) => group.related.iter().map(|&i| &all[i]).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Switches to the other view and selects a group related to the
+    /// selected one: the group the last jump came from when it is related,
+    /// so jumping twice goes back, otherwise the first.
+    pub fn jump_to_related(&mut self) {
+        let Some(row) = self.selected_row() else {
+            return;
+        };
+        let from = row.group.container().map(Path::to_path_buf);
+        let tab = if row.group.media_container.is_some() {
+            Tab::Downloads
+        } else {
+            Tab::Media
+        };
+        let related = self.related_groups(row.group);
+        let target = related
+            .iter()
+            .find(|g| g.container() == self.jump_origin.as_deref())
+            .or(related.first())
+            .and_then(|g| g.container())
+            .map(Path::to_path_buf);
+        let Some(target) = target else {
+            self.status_message = Some("No linked group in the other view".to_string());
+            return;
+        };
+
+        self.active_tab = tab;
+        self.jump_origin = from;
+        self.ensure_groups();
+        match self
+            .rows()
+            .iter()
+            .position(|r| r.depth == 0 && *r.path == *target)
+        {
+            Some(index) => self.selected_index = index,
+            None => {
+                self.selected_index = 0;
+                self.status_message =
+                    Some("The linked group is hidden by the current search or filters".to_string());
+            }
+        }
+    }
+
+    /// The media or download groups the active tab lists from.
+    fn tab_groups(&self) -> &[Group] {
         let groups = match self.active_tab {
-            Tab::Media => self.media_groups.as_ref().unwrap_or(&EMPTY_GROUPS),
-            Tab::MediaFolder(idx) => self.folder_groups.get(&idx).unwrap_or(&EMPTY_GROUPS),
-            Tab::Downloads => self.download_groups.as_ref().unwrap_or(&EMPTY_GROUPS),
+            Tab::Media | Tab::MediaFolder(_) => &self.media_groups,
+            Tab::Downloads | Tab::DownloadFolder(_) => &self.download_groups,
+        };
+        groups.as_deref().unwrap_or_default()
+    }
+
+    pub fn current_groups(&self) -> Vec<&Group> {
+        let all = self.tab_groups();
+        self.current_group_indices()
+            .into_iter()
+            .map(|i| &all[i])
+            .collect()
+    }
+
+    /// Indices into [`App::tab_groups`] of the listed groups, searched,
+    /// filtered and sorted.
+    fn current_group_indices(&self) -> Vec<usize> {
+        let all = self.tab_groups();
+        let groups: Vec<usize> = match self.active_tab {
+            Tab::Media | Tab::Downloads => (0..all.len()).collect(),
+            Tab::MediaFolder(idx) => self.folder_groups.get(&idx).cloned().unwrap_or_default(),
+            Tab::DownloadFolder(idx) => self
+                .download_folder_groups
+                .get(&idx)
+                .cloned()
+                .unwrap_or_default(),
         };
 
-        let mut filtered: Vec<&Group> = if self.search_query.is_empty() {
-            groups.iter().collect()
+        let mut filtered: Vec<usize> = if self.search_query.is_empty() {
+            groups
         } else {
             let query = self.search_query.to_lowercase();
             groups
-                .iter()
-                .filter(|g| g.title.to_lowercase().contains(&query))
+                .into_iter()
+                .filter(|&i| all[i].matches(&query))
                 .collect()
         };
 
+        filtered.retain(|&i| self.kind_filter.matches(all[i].kind));
+
         // Apply Filtering
-        filtered = match self.filter {
-            FilterMode::All => filtered,
-            FilterMode::Orphans => filtered
-                .into_iter()
-                .filter(|g| g.nodes.iter().any(|n| !(n.has_downloads && n.has_media)))
-                .collect(),
-            FilterMode::Hardlinked => filtered
-                .into_iter()
-                .filter(|g| g.nodes.iter().all(|n| n.has_downloads && n.has_media))
-                .collect(),
-        };
+        let nodes = &self.nodes;
+        filtered.retain(|&i| {
+            let mut files = all[i].files(nodes);
+            match self.filter {
+                FilterMode::All => true,
+                FilterMode::Orphans => {
+                    files.any(|n| !(n.incomplete || n.has_downloads && n.has_media))
+                }
+                FilterMode::Hardlinked => files.all(|n| n.has_downloads && n.has_media),
+                FilterMode::Duplicates => files.any(|n| !n.duplicates.is_empty()),
+                FilterMode::ExternalLinks => files.any(|n| n.external_links() > 0),
+                FilterMode::Incomplete => files.any(|n| n.incomplete),
+            }
+        });
 
-        match self.sort_by {
-            SortBy::Name => filtered.sort_by(|a, b| {
-                let cmp = a.title.cmp(&b.title);
-                if self.sort_order == SortOrder::Descending {
-                    cmp.reverse()
-                } else {
-                    cmp
+        // Same titles fall back to the year, then the season and episode
+        let by_name = |a: &Group, b: &Group| {
+            let titles = a.title.to_lowercase().cmp(&b.title.to_lowercase());
+            let (a, b) = (&a.release, &b.release);
+            titles
+                .then_with(|| a.year.cmp(&b.year))
+                .then_with(|| (a.season, a.episode).cmp(&(b.season, b.episode)))
+        };
+        filtered.sort_by(|&a, &b| {
+            let (a, b) = (&all[a], &all[b]);
+            let cmp = match self.sort_by {
+                SortBy::Name => by_name(a, b),
+                SortBy::Size => {
+                    let size_a: u64 = a.files(nodes).map(|n| n.size).sum();
+                    let size_b: u64 = b.files(nodes).map(|n| n.size).sum();
+                    size_a.cmp(&size_b).then_with(|| by_name(a, b))
                 }
-            }),
-            SortBy::Size => filtered.sort_by(|a, b| {
-                let size_a: u64 = a.nodes.iter().map(|n| n.size).sum();
-                let size_b: u64 = b.nodes.iter().map(|n| n.size).sum();
-                let cmp = size_a.cmp(&size_b).then_with(|| a.title.cmp(&b.title));
-                if self.sort_order == SortOrder::Descending {
-                    cmp.reverse()
-                } else {
-                    cmp
+                SortBy::DateAdded => {
+                    let date_a = a.files(nodes).filter_map(|n| n.modified).max();
+                    let date_b = b.files(nodes).filter_map(|n| n.modified).max();
+                    date_a.cmp(&date_b).then_with(|| by_name(a, b))
                 }
-            }),
-            SortBy::DateAdded => filtered.sort_by(|a, b| {
-                let date_a = a.nodes.iter().filter_map(|n| n.modified).max();
-                let date_b = b.nodes.iter().filter_map(|n| n.modified).max();
-                let cmp = date_a.cmp(&date_b).then_with(|| a.title.cmp(&b.title));
-                if self.sort_order == SortOrder::Descending {
-                    cmp.reverse()
-                } else {
-                    cmp
+                SortBy::Year => a
+                    .release
+                    .year
+                    .cmp(&b.release.year)
+                    .then_with(|| by_name(a, b)),
+                SortBy::Quality => a
+                    .release
+                    .resolution_rank()
+                    .cmp(&b.release.resolution_rank())
+                    .then_with(|| by_name(a, b)),
+            };
+            if self.sort_order == SortOrder::Descending {
+                cmp.reverse()
+            } else {
+                cmp
+            }
+        });
+
+        filtered
+    }
+
+    /// The listed groups, with the contents of expanded groups and folders
+    /// below each. Built once per change of the groups, the expanded set or
+    /// the view settings, and cheap to ask for again.
+    pub fn rows(&self) -> Vec<Row<'_>> {
+        let groups = self.tab_groups();
+        self.with_rows(|rows| rows.iter().map(|row| row.borrow(groups)).collect())
+    }
+
+    pub fn selected_row(&self) -> Option<Row<'_>> {
+        let groups = self.tab_groups();
+        self.with_rows(|rows| rows.get(self.selected_index).map(|row| row.borrow(groups)))
+    }
+
+    pub fn row_count(&self) -> usize {
+        self.with_rows(<[CachedRow]>::len)
+    }
+
+    fn with_rows<T>(&self, f: impl FnOnce(&[CachedRow]) -> T) -> T {
+        let view = RowView {
+            generation: self.rows_generation,
+            tab: self.active_tab,
+            search: self.search_query.clone(),
+            filter: self.filter,
+            kind: self.kind_filter,
+            sort_by: self.sort_by,
+            sort_order: self.sort_order,
+        };
+        let mut cache = self.row_cache.borrow_mut();
+        if cache.as_ref().is_none_or(|(built, _)| *built != view) {
+            *cache = Some((view, self.build_rows()));
+        }
+        f(&cache.as_ref().expect("rows were just built").1)
+    }
+
+    /// Marks the cached rows stale after the groups or the expanded set
+    /// changed.
+    fn invalidate_rows(&mut self) {
+        self.rows_generation += 1;
+    }
+
+    fn build_rows(&self) -> Vec<CachedRow> {
+        let groups = self.tab_groups();
+        let mut rows = Vec::new();
+        for index in self.current_group_indices() {
+            let group = &groups[index];
+            let Some(shared) = group
+                .media_container
+                .as_ref()
+                .or(group.downloads_container.as_ref())
+            else {
+                continue;
+            };
+            let container: &Path = shared;
+            // Expandable when anything lies below the container, without
+            // listing what
+            let is_dir = group
+                .files(&self.nodes)
+                .flat_map(FileNode::all_paths)
+                .any(|p| p != container && p.starts_with(container));
+            let expanded = is_dir && self.expanded.contains(container);
+            rows.push(CachedRow {
+                group: index,
+                path: shared.clone(),
+                depth: 0,
+                nodes: group.nodes.as_slice().into(),
+                is_dir,
+                expanded,
+            });
+            if expanded {
+                self.push_children(&mut rows, index, container, &group.nodes, 1);
+            }
+        }
+        rows
+    }
+
+    /// Adds a row for every folder and file directly in `dir`, folders
+    /// first, followed by the contents of the expanded ones.
+    fn push_children(
+        &self,
+        rows: &mut Vec<CachedRow>,
+        group: usize,
+        dir: &Path,
+        members: &[usize],
+        depth: usize,
+    ) {
+        // (is a file, path) -> nodes at or below it
+        let mut children: BTreeMap<(bool, PathBuf), Vec<usize>> = BTreeMap::new();
+        for &i in members {
+            for path in self.nodes[i].all_paths() {
+                let Ok(relative) = path.strip_prefix(dir) else {
+                    continue;
+                };
+                let mut components = relative.components();
+                let Some(first) = components.next() else {
+                    continue;
+                };
+                let is_file = components.next().is_none();
+                let child = children.entry((is_file, dir.join(first))).or_default();
+                if child.last() != Some(&i) {
+                    child.push(i);
                 }
-            }),
+            }
+        }
+        for ((is_file, path), nodes) in children {
+            let expanded = !is_file && self.expanded.contains(&path);
+            rows.push(CachedRow {
+                group,
+                path: path.as_path().into(),
+                depth,
+                nodes: nodes.as_slice().into(),
+                is_dir: !is_file,
+                expanded,
+            });
+            if expanded {
+                self.push_children(rows, group, &path, &nodes, depth + 1);
+            }
         }
+    }
 
-        filtered
+    /// Shows or hides what's inside the selected group or folder.
+    pub fn toggle_expanded(&mut self) {
+        if let Some(row) = self.selected_row().filter(|r| r.is_dir) {
+            let path = row.path.to_path_buf();
+            if !self.expanded.remove(&path) {
+                self.expanded.insert(path);
+            }
+            self.invalidate_rows();
+        }
+    }
+
+    pub fn expand(&mut self) {
+        if let Some(row) = self.selected_row().filter(|r| r.is_dir && !r.expanded) {
+            self.expanded.insert(row.path.to_path_buf());
+            self.invalidate_rows();
+        }
+    }
+
+    /// Collapses the selected row, or moves up to its parent when it is
+    /// already collapsed.
+    pub fn collapse(&mut self) {
+        let rows = self.rows();
+        let Some(row) = rows.get(self.selected_index) else {
+            return;
+        };
+        if row.expanded {
+            let path = row.path.clone();
+            self.expanded.remove(&*path);
+            self.invalidate_rows();
+        } else if row.depth > 0
+            && let Some(parent) = rows[..self.selected_index]
+                .iter()
+                .rposition(|r| r.depth < row.depth)
+        {
+            self.selected_index = parent;
+        }
+    }
+
+    /// Keeps the selection on the list after it shrank, regrouping first
+    /// when the groups were dropped so it is measured against the new list.
+    fn clamp_selection(&mut self) {
+        self.ensure_groups();
+        let len = self.row_count();
+        if self.selected_index >= len && len > 0 {
+            self.selected_index = len - 1;
+        }
     }
 
     pub fn select_next(&mut self) {
-        let len = self.current_groups().len();
+        let len = self.row_count();
         if len > 0 {
             self.selected_index = (self.selected_index + 1) % len;
         }
     }
 
     pub fn select_prev(&mut self) {
-        let len = self.current_groups().len();
+        let len = self.row_count();
         if len > 0 {
             if self.selected_index > 0 {
                 self.selected_index -= 1;
@@ -268,36 +934,61 @@ impl App {
         }
     }
 
+    /// Switches to the scanning state, superseding any scan still running.
+    pub fn begin_scan(
+        &mut self,
+        receiver: std::sync::mpsc::Receiver<crate::scanner::ScanEvent>,
+        cancel: CancelToken,
+    ) {
+        self.cancel_scan();
+        self.state = AppState::Scanning {
+            progress: ScanProgress::default(),
+            started: Instant::now(),
+            receiver,
+            cancel,
+        };
+    }
+
+    /// Aborts the running scan, keeping the nodes from the previous one.
+    pub fn cancel_scan(&mut self) {
+        if let AppState::Scanning { ref cancel, .. } = self.state {
+            cancel.cancel();
+            self.state = AppState::Ready;
+        }
+    }
+
+    pub fn is_scanning(&self) -> bool {
+        matches!(self.state, AppState::Scanning { .. })
+    }
+
     pub fn tick(&mut self) {
         if let AppState::Scanning {
-            ref mut processed,
+            ref mut progress,
             ref receiver,
+            ..
         } = self.state
         {
             while let Ok(event) = receiver.try_recv() {
                 match event {
-                    crate::scanner::ScanEvent::FileScanned => {
-                        *processed += 1;
+                    crate::scanner::ScanEvent::Progress(update) => {
+                        *progress = update;
                     }
-                    crate::scanner::ScanEvent::Finished(mut nodes) => {
-                        // Enrich nodes with torrent data
+                    crate::scanner::ScanEvent::Finished(mut nodes, report) => {
                         for node in &mut nodes {
-                            for path in &node.paths {
-                                let path_str = path.to_string_lossy();
-                                for torrent in &self.torrents {
-                                    if path_str.contains(&torrent.name) {
-                                        node.torrent_hash = Some(torrent.hash.clone());
-                                        node.is_seeding = torrent.state.contains("UP")
-                                            || torrent.state.contains("uploading");
-                                    }
-                                }
-                            }
+                            enrich_node(&self.torrents, self.incomplete_dir.as_deref(), node);
                         }
                         self.nodes = nodes;
+                        self.releases.clear();
+                        self.last_scan = Some(report);
+                        self.show_warnings = false;
                         self.refresh_groups();
                         self.state = AppState::Ready;
                         break;
                     }
+                    crate::scanner::ScanEvent::Cancelled => {
+                        self.state = AppState::Ready;
+                        break;
+                    }
                     crate::scanner::ScanEvent::Error(e) => {
                         self.state = AppState::Error(e);
                         break;
@@ -305,6 +996,136 @@ impl App {
                 }
             }
         }
+
+        if !self.pending_changes.is_empty() && !self.is_busy() {
+            let changes = std::mem::take(&mut self.pending_changes);
+            self.apply_changes(changes);
+        }
+    }
+
+    /// Whether nodes must stay put: a scan is about to replace them, or a
+    /// confirmation refers to the selected group.
+    fn is_busy(&self) -> bool {
+        self.is_scanning() || self.dialog_open()
+    }
+
+    /// Whether a delete or relink confirmation is open. It acts on the
+    /// selected row, so nothing may change the selection or the nodes.
+    pub fn dialog_open(&self) -> bool {
+        self.show_confirmation || self.show_relink
+    }
+
+    /// Applies changes reported by the watcher to the node list. Changes are
+    /// first collapsed to the last one per path, taking batches in the order
+    /// they arrived (the watcher sorts each batch by path), so a file written
+    /// and then deleted doesn't come back. Removals then go first, so a
+    /// rename's fresh stat decides the final link count. Only deleted paths
+    /// lower the link count; excluded ones are still links on disk.
+    pub fn apply_changes(&mut self, changes: Vec<FileChange>) {
+        if self.is_busy() {
+            self.pending_changes.extend(changes);
+            return;
+        }
+
+        // Path and whether it was deleted rather than excluded
+        let mut removed: Vec<(PathBuf, bool)> = Vec::new();
+        let mut upserted: BTreeMap<PathBuf, WatchedFile> = BTreeMap::new();
+        for change in changes {
+            let deleted = matches!(change, FileChange::Removed(_));
+            match change {
+                FileChange::Removed(path) | FileChange::Excluded(path) => {
+                    upserted.retain(|p, _| !p.starts_with(&path));
+                    removed.push((path, deleted));
+                }
+                FileChange::Upserted(file) => {
+                    upserted.insert(file.path.clone(), file);
+                }
+            }
+        }
+
+        self.releases.forget(
+            removed
+                .iter()
+                .map(|(p, _)| p)
+                .chain(upserted.keys())
+                .map(PathBuf::as_path),
+        );
+        for (path, deleted) in removed {
+            for node in &mut self.nodes {
+                let before = node.paths.len();
+                let before_symlinks = node.symlinks.len();
+                node.paths.retain(|p| !p.starts_with(&path));
+                node.symlinks.retain(|p| !p.starts_with(&path));
+                if node.paths.len() != before || node.symlinks.len() != before_symlinks {
+                    if deleted {
+                        node.nlink = node
+                            .nlink
+                            .saturating_sub((before - node.paths.len()) as u32);
+                    }
+                    update_locations(&self.config, node);
+                }
+            }
+        }
+
+        for file in upserted.into_values() {
+            // The path may now hold another inode, e.g. after a relink or an
+            // import that replaced the file.
+            for node in &mut self.nodes {
+                if node.key != file.key && node.paths.contains(&file.path) {
+                    node.paths.retain(|p| *p != file.path);
+                    update_locations(&self.config, node);
+                }
+            }
+            match self.nodes.iter_mut().find(|n| n.key == file.key) {
+                Some(node) => {
+                    if !node.paths.contains(&file.path) {
+                        node.paths.push(file.path);
+                        node.paths.sort();
+                    }
+                    node.nlink = file.nlink;
+                    node.size = file.size;
+                    node.modified = file.modified;
+                    update_locations(&self.config, node);
+                    enrich_node(&self.torrents, self.incomplete_dir.as_deref(), node);
+                }
+                None => {
+                    let mut node = FileNode {
+                        key: file.key,
+                        size: file.size,
+                        nlink: file.nlink,
+                        paths: vec![file.path],
+                        has_downloads: false,
+                        has_media: false,
+                        torrent_hash: None,
+                        is_seeding: false,
+                        modified: file.modified,
+                        kind: NodeKind::File,
+                        duplicates: Vec::new(),
+                        incomplete: false,
+                        symlinks: Vec::new(),
+                    };
+                    update_locations(&self.config, &mut node);
+                    enrich_node(&self.torrents, self.incomplete_dir.as_deref(), &mut node);
+                    self.nodes.push(node);
+                }
+            }
+        }
+
+        let gone: Vec<FileKey> = self
+            .nodes
+            .iter()
+            .filter(|n| n.all_paths().next().is_none())
+            .map(|n| n.key)
+            .collect();
+        if !gone.is_empty() {
+            self.nodes.retain(|n| n.all_paths().next().is_some());
+            for node in &mut self.nodes {
+                node.duplicates.retain(|k| !gone.contains(k));
+            }
+        }
+
+        self.refresh_groups();
+        self.clamp_selection();
     }
 
     pub fn quit(&mut self) {
@@ -323,6 +1144,11 @@ impl App {
         }
 
         tabs.push(Tab::Downloads);
+        if self.config.download_dirs.len() > 1 {
+            for i in 0..self.config.download_dirs.len() {
+                tabs.push(Tab::DownloadFolder(i));
+            }
+        }
         tabs
     }
 
@@ -335,7 +1161,7 @@ impl App {
     }
 
     // Jump to a specific tab index (0-based) from the UI/keyboard perspective
-    // 0: Media, 1..N: Folders (if any), Last: Downloads
+    // 0: Media, 1..N: Folders (if any), then Downloads and its folders (if any)
     pub fn set_tab_index(&mut self, index: usize) {
         let tabs = self.get_tabs_list();
         if index < tabs.len() {
@@ -344,18 +1170,28 @@ impl App {
         }
     }
 
+    /// Whether the files can be changed from here; explains why not in the
+    /// status bar otherwise.
+    fn check_local(&mut self) -> bool {
+        if let Some(source) = &self.remote_source {
+            self.status_message = Some(format!(
+                "Read-only: browsing {}, run this on the machine that holds the files",
+                source
+            ));
+        }
+        self.remote_source.is_none()
+    }
+
     pub fn request_delete(&mut self) {
-        let groups = self.current_groups();
-        if groups.is_empty() {
+        if !self.check_local() {
             return;
         }
-
-        if let Some(group) = groups.get(self.selected_index) {
+        if let Some(row) = self.selected_row() {
             let mut available = Vec::new();
             let mut has_downloads = false;
             let mut has_media = false;
 
-            for node in &group.nodes {
+            for node in row.files(&self.nodes) {
                 if node.has_downloads {
                     has_downloads = true;
                 }
@@ -380,10 +1216,63 @@ impl App {
 
             self.available_scopes = available;
             self.delete_scope = self.available_scopes[0];
+            self.include_incomplete = false;
             self.show_confirmation = true;
         }
     }
 
+    /// Space the pending delete would free, space that stays allocated
+    /// because the files also have hardlinks outside the scanned roots, and
+    /// the size of files still downloading, which are only deleted when
+    /// [`App::include_incomplete`] is set.
+    pub fn delete_preview(&self) -> (u64, u64, u64) {
+        let Some(row) = self.selected_row() else {
+            return (0, 0, 0);
+        };
+        let mut freed = 0;
+        let mut retained = 0;
+        let mut incomplete = 0;
+        for node in row.files(&self.nodes) {
+            if node.incomplete {
+                incomplete += node.size;
+                if !self.include_incomplete {
+                    continue;
+                }
+            }
+            let scoped = self.scoped_paths(&row, node);
+            let keeps_path = node.located_paths().iter().any(|p| !scoped.contains(&p));
+            if keeps_path {
+                continue;
+            }
+            if node.external_links() > 0 {
+                retained += node.size;
+            } else {
+                freed += node.size;
+            }
+        }
+        (freed, retained, incomplete)
+    }
+
+    /// The paths of `node` the pending delete takes. A folder or file row
+    /// only takes the paths below it from its own side, the media side for
+    /// a media group; the other side's paths are taken whole.
+    fn scoped_paths<'n>(&self, row: &Row, node: &'n FileNode) -> Vec<&'n PathBuf> {
+        let media_side = row.group.media_container.is_some();
+        node.all_paths()
+            .filter(|path| {
+                let download = self.config.is_download(path);
+                let media = self.config.media_dirs.iter().any(|m| path.starts_with(m));
+                let wanted = match self.delete_scope {
+                    DeleteScope::Downloads => download,
+                    DeleteScope::Media => media,
+                    DeleteScope::All => download || media,
+                };
+                let own_side = if media_side { media } else { download };
+                wanted && (row.depth == 0 || !own_side || path.starts_with(&row.path))
+            })
+            .collect()
+    }
+
     pub fn confirm_delete(&mut self) {
         self.execute_delete();
         self.show_confirmation = false;
@@ -393,102 +1282,167 @@ impl App {
         self.show_confirmation = false;
     }
 
-    fn execute_delete(&mut self) {
-        let group_title = if let Some(g) = self.current_groups().get(self.selected_index) {
-            g.title.clone()
-        } else {
+    pub fn request_relink(&mut self) {
+        if !self.check_local() {
             return;
-        };
+        }
+        let has_copies = self
+            .selected_row()
+            .is_some_and(|r| r.files(&self.nodes).any(|n| !n.duplicates.is_empty()));
+        if has_copies {
+            self.relink_target = RelinkTarget::Media;
+            self.show_relink = true;
+        }
+    }
 
-        let mut hashes_to_delete = Vec::new();
-        let mut paths_to_remove = Vec::new();
+    pub fn confirm_relink(&mut self) {
+        self.execute_relink();
+        self.show_relink = false;
+    }
 
-        // 1. Identify what needs to be deleted in the master nodes
-        for node in &mut self.nodes {
-            // Check if this node belongs to the selected group
-            let is_in_group = match self.active_tab {
-                Tab::Media => self.config.media_dirs.iter().any(|m| {
-                    node.paths.iter().any(|p| {
-                        if let Ok(rel) = p.strip_prefix(m) {
-                            if let Some(first) = rel.components().next() {
-                                return first.as_os_str().to_string_lossy() == group_title;
-                            }
-                        }
-                        false
-                    })
-                }),
-                Tab::MediaFolder(idx) => {
-                    if let Some(m) = self.config.media_dirs.get(idx) {
-                        node.paths.iter().any(|p| {
-                            if let Ok(rel) = p.strip_prefix(m) {
-                                if let Some(first) = rel.components().next() {
-                                    return first.as_os_str().to_string_lossy() == group_title;
-                                }
-                            }
-                            false
-                        })
-                    } else {
-                        false
-                    }
-                }
-                Tab::Downloads => {
-                    if let Some(ref download_dir) = self.config.download_dir {
-                        node.paths.iter().any(|p| {
-                            if let Ok(rel) = p.strip_prefix(download_dir) {
-                                if let Some(first) = rel.components().next() {
-                                    return first.as_os_str().to_string_lossy() == group_title;
-                                }
-                            }
-                            false
-                        })
+    pub fn cancel_relink(&mut self) {
+        self.show_relink = false;
+    }
+
+    /// Replaces every duplicate copy in the selected row with a hardlink and
+    /// merges each pair into a single linked node.
+    fn execute_relink(&mut self) {
+        let Some(row) = self.selected_row() else {
+            return;
+        };
+
+        // (download, media) pairs, whichever side the group was built from
+        let mut pairs: Vec<(FileKey, FileKey)> = row
+            .files(&self.nodes)
+            .flat_map(|node| {
+                node.duplicates.iter().map(move |copy| {
+                    if node.has_downloads {
+                        (node.key, *copy)
                     } else {
-                        false
+                        (*copy, node.key)
                     }
-                }
-            };
+                })
+            })
+            .collect();
+        pairs.sort_by_key(|&(d, m)| (d.dev, d.inode, m.dev, m.inode));
+        pairs.dedup();
 
-            if !is_in_group {
+        let mut relinked = 0;
+        let mut freed = 0;
+        let mut errors = Vec::new();
+        for (download, media) in pairs {
+            let (keep, replace) = match self.relink_target {
+                RelinkTarget::Media => (download, media),
+                RelinkTarget::Downloads => (media, download),
+            };
+            // Either side may already have been merged away by an earlier pair
+            let (Some(ki), Some(ri)) = (
+                self.nodes.iter().position(|n| n.key == keep),
+                self.nodes.iter().position(|n| n.key == replace),
+            ) else {
                 continue;
-            }
+            };
 
-            match self.delete_scope {
-                DeleteScope::Downloads => {
-                    if let Some(hash) = &node.torrent_hash {
-                        hashes_to_delete.push(hash.clone());
-                    } else if let Some(ref download_dir) = self.config.download_dir {
-                        for path in &node.paths {
-                            if path.starts_with(download_dir) {
-                                paths_to_remove.push(path.clone());
-                            }
-                        }
-                    }
-                }
-                DeleteScope::Media => {
-                    for path in &node.paths {
-                        if self.config.media_dirs.iter().any(|m| path.starts_with(m)) {
-                            paths_to_remove.push(path.clone());
-                        }
+            // A file only seen through symlinks has no path to link from
+            let Some(source) = self.nodes[ki].paths.first().cloned() else {
+                continue;
+            };
+            let mut moved = Vec::new();
+            let mut nlink = None;
+            for path in self.nodes[ri].paths.clone() {
+                match crate::relink::relink(&source, &path) {
+                    Ok(count) => {
+                        nlink = Some(count);
+                        moved.push(path);
                     }
+                    Err(e) => errors.push(format!("{}: {:#}", path.display(), e)),
                 }
-                DeleteScope::All => {
-                    if let Some(hash) = &node.torrent_hash {
-                        hashes_to_delete.push(hash.clone());
-                    } else if let Some(ref download_dir) = self.config.download_dir {
-                        for path in &node.paths {
-                            if path.starts_with(download_dir) {
-                                paths_to_remove.push(path.clone());
-                            }
-                        }
-                    }
-                    for path in &node.paths {
-                        if self.config.media_dirs.iter().any(|m| path.starts_with(m)) {
-                            paths_to_remove.push(path.clone());
-                        }
-                    }
+            }
+            let Some(nlink) = nlink else {
+                continue;
+            };
+            relinked += 1;
+
+            self.nodes[ri].paths.retain(|p| !moved.contains(p));
+            let kept = &mut self.nodes[ki];
+            kept.paths.extend(moved);
+            kept.paths.sort();
+            kept.nlink = nlink;
+            update_locations(&self.config, kept);
+            let replaced = &mut self.nodes[ri];
+            update_locations(&self.config, replaced);
+
+            if replaced.paths.is_empty() {
+                freed += replaced.size;
+                self.nodes.remove(ri);
+                for node in &mut self.nodes {
+                    node.duplicates.retain(|k| *k != replace);
                 }
             }
         }
 
+        let mut message = format!(
+            "Relinked {} copies, freed {}",
+            relinked,
+            crate::ui::format_size(freed)
+        );
+        if let Some(first) = errors.first() {
+            message.push_str(&format!(" | {} failed: {}", errors.len(), first));
+        }
+        self.status_message = Some(message);
+
+        self.refresh_groups();
+        self.clamp_selection();
+    }
+
+    fn execute_delete(&mut self) {
+        let Some(row) = self.selected_row() else {
+            return;
+        };
+        let members: HashSet<usize> = row
+            .nodes
+            .iter()
+            .copied()
+            .filter(|&i| self.include_incomplete || !self.nodes[i].incomplete)
+            .collect();
+
+        // A torrent is only removed through qBittorrent when all of its files
+        // are being deleted; a season or a file out of it is removed on disk
+        let mut unselected: HashMap<&String, usize> = HashMap::new();
+        for (i, node) in self.nodes.iter().enumerate() {
+            if let Some(hash) = &node.torrent_hash {
+                *unselected.entry(hash).or_default() += usize::from(!members.contains(&i));
+            }
+        }
+        let whole_torrents: HashSet<&String> = unselected
+            .into_iter()
+            .filter(|&(_, count)| count == 0)
+            .map(|(hash, _)| hash)
+            .collect();
+
+        let mut hashes_to_delete = Vec::new();
+        let mut paths_to_remove = Vec::new();
+
+        // 1. Identify what needs to be deleted in the master nodes
+        for node in members.iter().map(|&i| &self.nodes[i]) {
+            let scoped = self.scoped_paths(&row, node);
+            // qBittorrent removes a whole torrent's download files itself
+            if self.delete_scope != DeleteScope::Media
+                && let Some(hash) = &node.torrent_hash
+                && whole_torrents.contains(hash)
+            {
+                hashes_to_delete.push(hash.clone());
+                paths_to_remove.extend(
+                    scoped
+                        .into_iter()
+                        .filter(|p| !self.config.is_download(p))
+                        .cloned(),
+                );
+            } else {
+                paths_to_remove.extend(scoped.into_iter().cloned());
+            }
+        }
+
         // 2. Perform actual disk deletion for non-torrent paths
         for path in &paths_to_remove {
             if path.exists() {
@@ -504,29 +1458,57 @@ impl App {
         }
 
         // 4. Update the master nodes state
+        self.releases
+            .forget(paths_to_remove.iter().map(PathBuf::as_path));
         for node in &mut self.nodes {
+            let before = node.paths.len();
             node.paths.retain(|p| !paths_to_remove.contains(p));
+            node.symlinks.retain(|p| !paths_to_remove.contains(p));
+            node.nlink = node
+                .nlink
+                .saturating_sub((before - node.paths.len()) as u32);
 
             // Re-calculate flags
-            node.has_downloads = if let Some(ref download_dir) = self.config.download_dir {
-                node.paths.iter().any(|p| p.starts_with(download_dir))
-            } else {
-                false
-            };
-            node.has_media = node
-                .paths
-                .iter()
-                .any(|p| self.config.media_dirs.iter().any(|m| p.starts_with(m)));
+            update_locations(&self.config, node);
         }
 
         // 5. Cleanup empty nodes
-        self.nodes.retain(|n| !n.paths.is_empty());
+        self.nodes.retain(|n| n.all_paths().next().is_some());
 
         // 6. Refresh views
         self.refresh_groups();
 
-        if self.selected_index >= self.current_groups().len() && !self.current_groups().is_empty() {
-            self.selected_index = self.current_groups().len() - 1;
+        self.clamp_selection();
+    }
+}
+
+/// Marks a node as belonging to a torrent when one of its paths contains the
+/// torrent's name, and as incomplete while it is still being downloaded.
+fn enrich_node(torrents: &[TorrentInfo], incomplete_dir: Option<&Path>, node: &mut FileNode) {
+    let incomplete = node
+        .all_paths()
+        .any(|p| is_partial(p) || incomplete_dir.is_some_and(|dir| p.starts_with(dir)));
+    node.incomplete = incomplete;
+    for path in node.paths.iter().chain(&node.symlinks) {
+        let path_str = path.to_string_lossy();
+        for torrent in torrents {
+            if path_str.contains(&torrent.name) {
+                node.torrent_hash = Some(torrent.hash.clone());
+                node.is_seeding =
+                    torrent.state.contains("UP") || torrent.state.contains("uploading");
+                node.incomplete |= torrent.progress < 1.0;
+            }
         }
     }
 }
+
+/// Recomputes which roots a node is visible from after its paths changed.
+fn update_locations(config: &Config, node: &mut FileNode) {
+    let paths = node.located_paths();
+    let has_downloads = paths.iter().any(|p| config.is_download(p));
+    let has_media = paths
+        .iter()
+        .any(|p| config.media_dirs.iter().any(|m| p.starts_with(m)));
+    node.has_downloads = has_downloads;
+    node.has_media = has_media;
+}
@@ -0,0 +1,512 @@
+use crate::config::{CacheCommand, Config};
+use crate::duplicates::FileHashes;
+use crate::fsid::MountTable;
+use crate::scanner::FileKey;
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+#[cfg(unix)]
+use std::os::unix::fs::MetadataExt;
+
+/// Bumped whenever the layout of a cache file changes. Files written with
+/// another version are discarded and rebuilt by the next scan.
+pub const CACHE_VERSION: u32 = 1;
+
+/// Leading bytes of every cache file, so anything else in the directory is
+/// never mistaken for one.
+const MAGIC: &[u8; 8] = b"RTTDYSC\0";
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CacheEntry {
+    pub key: FileKey,
+    pub nlink: u32,
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+    pub changed: Option<ChangeStamp>,
+    /// Content hashes from duplicate detection and the stamp the file had
+    /// when they were taken; stale once the stamp moves on.
+    #[serde(default)]
+    pub hashes: Option<(ChangeStamp, FileHashes)>,
+}
+
+/// Inode number and status-change time of a file. Adding or removing a
+/// hardlink bumps the ctime and replacing the file changes the inode, so a
+/// matching stamp means the cached key and link count are still current.
+/// Windows metadata has no change time; entries there fall back to size and
+/// modification time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChangeStamp {
+    inode: u64,
+    ctime: i64,
+    ctime_nsec: i64,
+}
+
+#[cfg(unix)]
+pub fn change_stamp(metadata: &fs::Metadata) -> Option<ChangeStamp> {
+    Some(ChangeStamp {
+        inode: metadata.ino(),
+        ctime: metadata.ctime(),
+        ctime_nsec: metadata.ctime_nsec(),
+    })
+}
+
+#[cfg(windows)]
+pub fn change_stamp(_metadata: &fs::Metadata) -> Option<ChangeStamp> {
+    None
+}
+
+/// A directory's listing as of its last `read_dir`. Creating, removing or
+/// renaming a child bumps the directory mtime, so while the mtime is unchanged
+/// the walker replays this listing instead of reading the directory. Each
+/// listed file is still `lstat`-ed and checked against its [`ChangeStamp`],
+/// which catches files rewritten in place and links added elsewhere.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct DirRecord {
+    pub modified: Option<SystemTime>,
+    pub files: Vec<OsString>,
+    pub subdirs: Vec<OsString>,
+    pub symlinks: Vec<OsString>,
+}
+
+/// Cached state of every root of a scan, in the same order as the roots.
+#[derive(Default)]
+pub struct ScanCache {
+    pub roots: Vec<RootCache>,
+    mounts: MountTable,
+}
+
+/// What the last scan saw below one root. Paths are absolute in memory and
+/// stored relative to the root on disk.
+#[derive(Default)]
+pub struct RootCache {
+    pub root: PathBuf,
+    pub entries: HashMap<PathBuf, CacheEntry>,
+    pub dirs: HashMap<PathBuf, DirRecord>,
+    /// Number of files found below this root by the last completed scan.
+    pub file_count: Option<usize>,
+    /// Whether anything changed since loading, i.e. the file must be written.
+    dirty: bool,
+}
+
+/// Leads every cache file, followed by a [`StoredRoot`].
+#[derive(Serialize, Deserialize)]
+struct CacheHeader {
+    version: u32,
+    root: OsString,
+    /// Device number of the root when the file was written.
+    dev: Option<u64>,
+    /// Filesystem behind each device number used by the entries, where it
+    /// could be identified. See [`MountTable`].
+    devices: Vec<(u64, String)>,
+}
+
+#[derive(Deserialize)]
+struct StoredRoot {
+    file_count: Option<usize>,
+    entries: Vec<(OsString, CacheEntry)>,
+    dirs: Vec<(OsString, DirRecord)>,
+}
+
+/// Borrowed twin of [`StoredRoot`] so saving doesn't clone the whole cache.
+#[derive(Serialize)]
+struct StoredRootRef<'a> {
+    file_count: Option<usize>,
+    entries: Vec<(&'a OsStr, &'a CacheEntry)>,
+    dirs: Vec<(&'a OsStr, &'a DirRecord)>,
+}
+
+impl ScanCache {
+    /// Loads the cache of each root. Missing, unreadable or outdated files
+    /// give an empty cache for that root, which the next save replaces.
+    pub fn load(roots: &[PathBuf]) -> Self {
+        let mounts = MountTable::load();
+        Self {
+            roots: roots
+                .iter()
+                .map(|root| RootCache::load(root, &mounts))
+                .collect(),
+            mounts,
+        }
+    }
+
+    /// Writes every root that changed since it was loaded.
+    pub fn save(&mut self) -> Result<()> {
+        for root in &mut self.roots {
+            if root.dirty {
+                root.save(&self.mounts)?;
+                root.dirty = false;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn entry(&self, root: usize, path: &Path) -> Option<&CacheEntry> {
+        self.roots[root].entries.get(path)
+    }
+
+    pub fn unchanged_dir(
+        &self,
+        root: usize,
+        dir: &Path,
+        modified: Option<SystemTime>,
+    ) -> Option<&DirRecord> {
+        modified?;
+        self.roots[root]
+            .dirs
+            .get(dir)
+            .filter(|record| record.modified == modified)
+    }
+
+    /// Content hashes of a file, as long as they were taken with the change
+    /// stamp the file has now.
+    pub fn hashes(&self, path: &Path) -> Option<FileHashes> {
+        self.roots.iter().find_map(|root| {
+            let entry = root.entries.get(path)?;
+            let (changed, hashes) = entry.hashes?;
+            (entry.changed == Some(changed)).then_some(hashes)
+        })
+    }
+
+    /// Stores a file's content hashes under its current change stamp. Files
+    /// without one, e.g. on Windows, are hashed again on every scan.
+    pub fn set_hashes(&mut self, path: &Path, hashes: FileHashes) {
+        for root in &mut self.roots {
+            if let Some(entry) = root.entries.get_mut(path)
+                && let Some(changed) = entry.changed
+                && entry.hashes != Some((changed, hashes))
+            {
+                entry.hashes = Some((changed, hashes));
+                root.mark_dirty();
+            }
+        }
+    }
+
+    /// File count of the last completed scan over all roots.
+    pub fn file_count(&self) -> Option<usize> {
+        self.roots.iter().map(|r| r.file_count).sum()
+    }
+}
+
+impl RootCache {
+    /// Loads the cache of `root`, translating cached device numbers to the
+    /// ones the same filesystems have now. Entries on filesystems that are no
+    /// longer mounted are dropped, and the whole cache is discarded when the
+    /// root itself now lives on a different filesystem.
+    fn load(root: &Path, mounts: &MountTable) -> Self {
+        let empty = Self {
+            root: root.to_path_buf(),
+            dirty: true,
+            ..Default::default()
+        };
+        let Some((header, stored)) = cache_file(root).and_then(|path| read_cache_file(&path).ok())
+        else {
+            return empty;
+        };
+        if Path::new(&header.root) != root_key(root) {
+            return empty;
+        }
+
+        let moved: HashMap<u64, Option<u64>> = header
+            .devices
+            .iter()
+            .map(|(dev, identity)| (*dev, mounts.device(identity)))
+            .collect();
+        let root_dev = device_of(root);
+        // Devices that couldn't be identified are only trusted while the
+        // root's own device number is unchanged.
+        let unchanged = header.dev == root_dev;
+        let current_dev = |cached: u64| match moved.get(&cached) {
+            Some(now) => *now,
+            None => unchanged.then_some(cached),
+        };
+        let same_root = match (header.dev, root_dev) {
+            (Some(cached), Some(now)) => current_dev(cached) == Some(now),
+            (cached, now) => cached == now,
+        };
+        if !same_root {
+            return empty;
+        }
+
+        let total = stored.entries.len();
+        let entries: HashMap<PathBuf, CacheEntry> = stored
+            .entries
+            .into_iter()
+            .filter_map(|(path, mut entry)| {
+                entry.key.dev = current_dev(entry.key.dev)?;
+                Some((absolute(root, path), entry))
+            })
+            .collect();
+        Self {
+            root: root.to_path_buf(),
+            dirty: entries.len() != total || !unchanged,
+            entries,
+            dirs: stored
+                .dirs
+                .into_iter()
+                .map(|(path, record)| (absolute(root, path), record))
+                .collect(),
+            file_count: stored.file_count,
+        }
+    }
+
+    pub fn mark_dirty(&mut self) {
+        self.dirty = true;
+    }
+
+    /// Drops directories that were not visited by the last scan and entries
+    /// for files it did not see.
+    pub fn prune(&mut self, dirs: &HashSet<&Path>, files: &HashSet<&Path>) {
+        let before = (self.dirs.len(), self.entries.len());
+        self.dirs.retain(|path, _| dirs.contains(path.as_path()));
+        self.entries
+            .retain(|path, _| files.contains(path.as_path()));
+        if (self.dirs.len(), self.entries.len()) != before {
+            self.dirty = true;
+        }
+    }
+
+    fn relative<'a>(&self, path: &'a Path) -> &'a OsStr {
+        path.strip_prefix(&self.root).unwrap_or(path).as_os_str()
+    }
+
+    fn save(&self, mounts: &MountTable) -> Result<()> {
+        let path = cache_file(&self.root).context("no cache directory available")?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let dev = device_of(&self.root);
+        let mut devices: Vec<u64> = self.entries.values().map(|e| e.key.dev).collect();
+        devices.extend(dev);
+        devices.sort_unstable();
+        devices.dedup();
+        let header = CacheHeader {
+            version: CACHE_VERSION,
+            root: root_key(&self.root).into_os_string(),
+            dev,
+            devices: devices
+                .into_iter()
+                .filter_map(|dev| Some((dev, mounts.identity(dev)?.to_string())))
+                .collect(),
+        };
+        let stored = StoredRootRef {
+            file_count: self.file_count,
+            entries: self
+                .entries
+                .iter()
+                .map(|(path, entry)| (self.relative(path), entry))
+                .collect(),
+            dirs: self
+                .dirs
+                .iter()
+                .map(|(path, record)| (self.relative(path), record))
+                .collect(),
+        };
+
+        // Write to a temporary file first so a scan starting while this one
+        // saves never reads a half-written cache.
+        let tmp_path = path.with_extension("tmp");
+        let mut writer = BufWriter::new(fs::File::create(&tmp_path)?);
+        writer.write_all(MAGIC)?;
+        bincode::serialize_into(&mut writer, &header)?;
+        bincode::serialize_into(&mut writer, &stored)?;
+        writer.flush()?;
+        drop(writer);
+        fs::rename(tmp_path, path)?;
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+fn device_of(path: &Path) -> Option<u64> {
+    fs::metadata(path).ok().map(|m| m.dev())
+}
+
+#[cfg(windows)]
+fn device_of(_path: &Path) -> Option<u64> {
+    None
+}
+
+fn absolute(root: &Path, relative: OsString) -> PathBuf {
+    if relative.is_empty() {
+        root.to_path_buf()
+    } else {
+        root.join(relative)
+    }
+}
+
+fn read_cache_file(path: &Path) -> Result<(CacheHeader, StoredRoot)> {
+    let mut reader = BufReader::new(fs::File::open(path)?);
+    let mut magic = [0; MAGIC.len()];
+    reader.read_exact(&mut magic)?;
+    if &magic != MAGIC {
+        bail!("not a ratatidy cache file");
+    }
+    let header: CacheHeader = bincode::deserialize_from(&mut reader)?;
+    if header.version != CACHE_VERSION {
+        bail!("cache format version {}", header.version);
+    }
+    let stored = bincode::deserialize_from(&mut reader)?;
+    Ok((header, stored))
+}
+
+pub fn cache_dir() -> Option<PathBuf> {
+    directories::ProjectDirs::from("com", "ratatidy", "ratatidy")
+        .map(|dirs| dirs.cache_dir().to_path_buf())
+}
+
+/// The form of `root` that cache files are keyed and labelled by, so the
+/// same directory finds the same cache whether it was configured as a
+/// relative path, through a symlink or from the `cache` subcommands.
+fn root_key(root: &Path) -> PathBuf {
+    fs::canonicalize(root).unwrap_or_else(|_| root.to_path_buf())
+}
+
+/// Cache files are named after a hash of their root, so each root can be
+/// loaded, saved and cleared on its own.
+fn cache_file(root: &Path) -> Option<PathBuf> {
+    let hash = xxhash_rust::xxh3::xxh3_64(root_key(root).as_os_str().as_encoded_bytes());
+    Some(
+        cache_dir()?
+            .join("roots")
+            .join(format!("{:016x}.bin", hash)),
+    )
+}
+
+fn cache_files() -> Vec<PathBuf> {
+    let Some(dir) = cache_dir() else {
+        return Vec::new();
+    };
+    let mut files: Vec<PathBuf> = fs::read_dir(dir.join("roots"))
+        .into_iter()
+        .flatten()
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "bin"))
+        .collect();
+    files.sort();
+    files
+}
+
+fn configured_roots(config: &Config) -> Vec<PathBuf> {
+    config
+        .download_dirs
+        .iter()
+        .map(|d| &d.path)
+        .chain(&config.media_dirs)
+        .map(|root| root_key(root))
+        .collect()
+}
+
+/// Runs one of the `ratatidy cache` subcommands.
+pub fn run(command: &CacheCommand, config: &Config) -> Result<()> {
+    match command {
+        CacheCommand::Stats => stats(config),
+        CacheCommand::Clear { root } => clear(root.as_deref()),
+        CacheCommand::Prune => prune(config),
+    }
+}
+
+fn stats(config: &Config) -> Result<()> {
+    let configured = configured_roots(config);
+    let files = cache_files();
+    if files.is_empty() {
+        println!("No scan cache yet.");
+        return Ok(());
+    }
+
+    let mut total_bytes = 0;
+    for path in files {
+        let bytes = fs::metadata(&path).map(|m| m.len()).unwrap_or(0);
+        total_bytes += bytes;
+        match read_cache_file(&path) {
+            Ok((header, stored)) => {
+                let root = PathBuf::from(header.root);
+                let state = if !configured.contains(&root) {
+                    " (not configured, removed by prune)"
+                } else {
+                    ""
+                };
+                println!("{}{}", root.display(), state);
+                let filesystem = header
+                    .dev
+                    .and_then(|dev| header.devices.iter().find(|(d, _)| *d == dev))
+                    .map(|(dev, identity)| format!("{} (device {})", identity, dev))
+                    .unwrap_or_else(|| "not identified".to_string());
+                println!("  filesystem: {}", filesystem);
+                println!(
+                    "  {} files, {} directories cached, {}, {} on disk",
+                    stored.entries.len(),
+                    stored.dirs.len(),
+                    stored
+                        .file_count
+                        .map(|n| format!("last scan found {} files", n))
+                        .unwrap_or_else(|| "no completed scan".to_string()),
+                    crate::ui::format_size(bytes)
+                );
+            }
+            Err(e) => println!("{}: unreadable ({:#})", path.display(), e),
+        }
+    }
+    println!(
+        "Format version {}, {} total",
+        CACHE_VERSION,
+        crate::ui::format_size(total_bytes)
+    );
+    Ok(())
+}
+
+fn clear(root: Option<&Path>) -> Result<()> {
+    let files = match root {
+        Some(root) => cache_file(root)
+            .into_iter()
+            .filter(|p| p.exists())
+            .collect(),
+        None => cache_files(),
+    };
+    for path in &files {
+        fs::remove_file(path).with_context(|| path.display().to_string())?;
+    }
+    println!("Removed {} cache file(s).", files.len());
+    Ok(())
+}
+
+/// Removes caches of roots that are no longer configured or no longer exist,
+/// and entries for files and directories that have disappeared since the
+/// last scan.
+fn prune(config: &Config) -> Result<()> {
+    let configured = configured_roots(config);
+    let mounts = MountTable::load();
+    for path in cache_files() {
+        let root = match read_cache_file(&path) {
+            Ok((header, _)) => PathBuf::from(header.root),
+            Err(_) => {
+                fs::remove_file(&path)?;
+                println!("{}: unreadable or outdated, removed", path.display());
+                continue;
+            }
+        };
+        if !configured.contains(&root) || !root.is_dir() {
+            fs::remove_file(&path)?;
+            println!("{}: no longer scanned, removed", root.display());
+            continue;
+        }
+
+        let mut cache = RootCache::load(&root, &mounts);
+        let before = (cache.entries.len(), cache.dirs.len());
+        cache
+            .entries
+            .retain(|path, _| fs::symlink_metadata(path).is_ok());
+        cache.dirs.retain(|path, _| path.is_dir());
+        let removed = before.0 - cache.entries.len() + before.1 - cache.dirs.len();
+        if removed > 0 {
+            cache.save(&mounts)?;
+        }
+        println!("{}: {} stale entries removed", root.display(), removed);
+    }
+    Ok(())
+}
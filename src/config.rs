@@ -1,14 +1,21 @@
+use crate::grouping::{GroupKind, StrategyKind};
 use clap::Parser;
 use directories::ProjectDirs;
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 #[derive(Parser, Debug, Serialize, Deserialize, Clone)]
 #[command(author, version, about, long_about = None)]
 pub struct Config {
-    /// Directory where torrents are downloaded
-    #[arg(short, long, env = "RATATIDY_DOWNLOAD_DIR")]
-    pub download_dir: Option<PathBuf>,
+    /// Directories where torrents are downloaded (comma separated, `label=path` to name a tab)
+    #[arg(
+        short,
+        long = "download-dir",
+        env = "RATATIDY_DOWNLOAD_DIR",
+        value_delimiter = ','
+    )]
+    #[serde(default, alias = "download_dir", deserialize_with = "one_or_many")]
+    pub download_dirs: Vec<DownloadDir>,
 
     /// Directories containing your media library (comma separated)
     #[arg(short, long, env = "RATATIDY_MEDIA_DIRS", value_delimiter = ',')]
@@ -26,12 +33,238 @@ pub struct Config {
     #[arg(long, default_value_t = false, env = "RATATIDY_DRY_RUN")]
     pub dry_run: bool,
 
-    /// Video file extensions to scan (comma separated)
+    /// Video file extensions to scan (comma separated, empty to scan every file)
     #[arg(long, value_delimiter = ',', default_value = "mkv,mp4,avi,mov")]
     pub video_extensions: Vec<String>,
 
+    /// Glob patterns, relative to each root, a file must match to be scanned (comma separated)
+    #[arg(long, value_delimiter = ',', env = "RATATIDY_INCLUDE")]
+    #[serde(default)]
+    pub include: Vec<String>,
+
+    /// Gitignore-style patterns for files and folders to skip (comma separated)
+    #[arg(long, value_delimiter = ',', env = "RATATIDY_EXCLUDE")]
+    #[serde(default)]
+    pub exclude: Vec<String>,
+
+    /// Extra include/exclude patterns for a single root (config file only)
+    #[arg(skip)]
+    #[serde(default)]
+    pub root_filters: Vec<RootFilterConfig>,
+
+    /// Kind of every group in a media folder, for folders the layout doesn't
+    /// tell apart (config file only)
+    #[arg(skip)]
+    #[serde(default)]
+    pub media_kinds: Vec<MediaKindConfig>,
+
+    /// How groups are found below a media or download folder, for layouts
+    /// deeper than one folder per title (config file only)
+    #[arg(skip)]
+    #[serde(default)]
+    pub grouping: Vec<GroupingConfig>,
+
+    /// How to treat symlinks while scanning
+    #[arg(long, value_enum, default_value = "ignore", env = "RATATIDY_SYMLINKS")]
+    #[serde(default)]
+    pub symlinks: SymlinkPolicy,
+
+    /// Hash same-sized download and media files to find copies that should be hardlinks
+    #[arg(long, default_value_t = true, action = clap::ArgAction::Set, env = "RATATIDY_DETECT_DUPLICATES")]
+    #[serde(default = "default_true")]
+    pub detect_duplicates: bool,
+
+    /// Stay on the filesystem of each root instead of descending into mount points
+    #[arg(long, env = "RATATIDY_ONE_FILE_SYSTEM")]
+    #[serde(default)]
+    pub one_file_system: bool,
+
+    /// Watch the roots and update the list as files are added, removed or relinked
+    #[arg(long, env = "RATATIDY_WATCH")]
+    #[serde(default)]
+    pub watch: bool,
+
+    /// Seconds between rescans when --watch can't use inotify (0 = never)
+    #[arg(long, default_value_t = 300, env = "RATATIDY_RESCAN_INTERVAL")]
+    #[serde(default = "default_rescan_interval")]
+    pub rescan_interval: u64,
+
+    /// Number of scan snapshots to keep for the changes view (0 = don't save any)
+    #[arg(long, default_value_t = 50, env = "RATATIDY_SNAPSHOTS")]
+    #[serde(default = "default_snapshots")]
+    pub snapshots: usize,
+
+    /// Number of threads used to walk directories (0 = one per CPU)
+    #[arg(long, default_value_t = 0, env = "RATATIDY_SCAN_THREADS")]
+    #[serde(default)]
+    pub scan_threads: usize,
+
+    /// Folder where unfinished downloads are kept (default: qBittorrent's incomplete folder)
+    #[arg(long, env = "RATATIDY_INCOMPLETE_DIR")]
+    #[serde(default)]
+    pub incomplete_dir: Option<PathBuf>,
+
+    /// Browse a scan exported with `ratatidy scan --export` instead of scanning (read-only)
+    #[arg(long, env = "RATATIDY_IMPORT")]
+    #[serde(skip)]
+    pub import: Option<PathBuf>,
+
     #[command(flatten)]
     pub qbittorrent: QBittorrentConfig,
+
+    #[command(subcommand)]
+    #[serde(skip)]
+    pub command: Option<Command>,
+}
+
+#[derive(clap::Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Scan without the TUI, e.g. on a server, and print a summary
+    Scan {
+        /// Write the scanned nodes to this JSON file, to browse with --import elsewhere
+        #[arg(long)]
+        export: Option<PathBuf>,
+    },
+    /// Inspect or maintain the scan cache
+    Cache {
+        #[command(subcommand)]
+        action: CacheCommand,
+    },
+}
+
+#[derive(clap::Subcommand, Debug, Clone)]
+pub enum CacheCommand {
+    /// Show the cached roots, their entry counts and size on disk
+    Stats,
+    /// Delete the whole cache, or only the cache of one root
+    Clear { root: Option<PathBuf> },
+    /// Drop caches of roots that are no longer scanned and entries for files that are gone
+    Prune,
+}
+
+/// A download root, optionally named for its tab. Written as `path` or
+/// `label=path`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DownloadDir {
+    pub path: PathBuf,
+    pub label: Option<String>,
+}
+
+impl DownloadDir {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path, label: None }
+    }
+
+    /// The label, or the folder name when there is none.
+    pub fn name(&self) -> String {
+        match &self.label {
+            Some(label) => label.clone(),
+            None => self
+                .path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_string(),
+        }
+    }
+}
+
+impl std::str::FromStr for DownloadDir {
+    type Err = std::convert::Infallible;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // A prefix with a path separator is part of the path, e.g. `/data/a=b`.
+        let labelled = s
+            .split_once('=')
+            .filter(|(label, _)| !label.contains(std::path::is_separator));
+        Ok(match labelled {
+            Some((label, path)) => Self {
+                path: PathBuf::from(path.trim()),
+                label: Some(label.trim().to_string()).filter(|l| !l.is_empty()),
+            },
+            None => Self::new(PathBuf::from(s.trim())),
+        })
+    }
+}
+
+impl std::fmt::Display for DownloadDir {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.label {
+            Some(label) => write!(f, "{}={}", label, self.path.display()),
+            None => write!(f, "{}", self.path.display()),
+        }
+    }
+}
+
+impl Serialize for DownloadDir {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for DownloadDir {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Ok(s.parse().unwrap_or_else(|e| match e {}))
+    }
+}
+
+/// Accepts a single download directory, as written by older versions, or a
+/// list.
+fn one_or_many<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<DownloadDir>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(DownloadDir),
+        Many(Vec<DownloadDir>),
+    }
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(dir) => vec![dir],
+        OneOrMany::Many(dirs) => dirs,
+    })
+}
+
+/// Patterns that only apply below `path`, on top of the global ones.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct RootFilterConfig {
+    pub path: PathBuf,
+    #[serde(default)]
+    pub include: Vec<String>,
+    #[serde(default)]
+    pub exclude: Vec<String>,
+}
+
+/// Movie, series or other for everything below a media folder, instead of
+/// detecting it from the folder layout.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MediaKindConfig {
+    pub path: PathBuf,
+    pub kind: GroupKind,
+}
+
+/// The grouping strategy for everything below a media or download folder.
+/// Folders without one are grouped by their first level.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct GroupingConfig {
+    pub path: PathBuf,
+    pub strategy: StrategyKind,
+    /// Folder levels below `path` that make a group, for the `depth` strategy.
+    #[serde(default = "default_depth")]
+    pub depth: usize,
+}
+
+#[derive(clap::ValueEnum, Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum SymlinkPolicy {
+    /// Skip symlinks, only reporting broken ones as scan warnings
+    #[default]
+    Ignore,
+    /// Scan through symlinks as if they were the files and folders they point to
+    Follow,
+    /// List each symlink as its own node
+    Report,
 }
 
 #[derive(clap::ValueEnum, Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
@@ -60,6 +293,22 @@ pub struct QBittorrentConfig {
     pub password: Option<String>,
 }
 
+fn default_true() -> bool {
+    true
+}
+
+fn default_depth() -> usize {
+    1
+}
+
+fn default_snapshots() -> usize {
+    50
+}
+
+fn default_rescan_interval() -> u64 {
+    300
+}
+
 impl QBittorrentConfig {
     pub fn is_configured(&self) -> bool {
         self.username.is_some() && self.password.is_some()
@@ -71,6 +320,15 @@ impl Config {
         Self::parse()
     }
 
+    pub fn download_paths(&self) -> Vec<PathBuf> {
+        self.download_dirs.iter().map(|d| d.path.clone()).collect()
+    }
+
+    /// Whether `path` is inside one of the download directories.
+    pub fn is_download(&self, path: &Path) -> bool {
+        self.download_dirs.iter().any(|d| path.starts_with(&d.path))
+    }
+
     pub fn config_path() -> Option<PathBuf> {
         ProjectDirs::from("", "", "ratatidy").map(|dirs| dirs.config_dir().join("config.toml"))
     }
@@ -97,17 +355,32 @@ impl Config {
 impl Default for Config {
     fn default() -> Self {
         Self {
-            download_dir: None,
+            download_dirs: vec![],
             media_dirs: vec![],
             delete_mode: DeleteMode::Container,
             trash_dir: None,
             dry_run: false,
             video_extensions: vec!["mkv".into(), "mp4".into(), "avi".into(), "mov".into()],
+            include: vec![],
+            exclude: vec![],
+            root_filters: vec![],
+            media_kinds: vec![],
+            grouping: vec![],
+            symlinks: SymlinkPolicy::Ignore,
+            detect_duplicates: true,
+            one_file_system: false,
+            watch: false,
+            rescan_interval: 300,
+            snapshots: 50,
+            scan_threads: 0,
             qbittorrent: QBittorrentConfig {
                 url: "http://localhost:8080".into(),
                 username: None,
                 password: None,
             },
+            incomplete_dir: None,
+            import: None,
+            command: None,
         }
     }
 }
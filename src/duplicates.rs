@@ -0,0 +1,227 @@
+use crate::scanner::{CancelToken, FileKey, FileNode, NodeKind, ScanIssue};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::Path;
+use xxhash_rust::xxh3::Xxh3;
+
+/// Bytes read from each end of a file for the partial hash.
+const PARTIAL_CHUNK: u64 = 64 * 1024;
+
+/// Outcome of a duplicate pass over the scanned nodes.
+#[derive(Debug, Default)]
+pub struct DuplicateReport {
+    /// For every node with a copy on the other side, the keys of those copies.
+    pub copies: HashMap<FileKey, Vec<FileKey>>,
+    pub issues: Vec<ScanIssue>,
+    /// Every hash known after the pass, cached or computed, for the cache.
+    pub hashes: HashMap<FileKey, FileHashes>,
+}
+
+/// Content hashes of one file, as far as the narrowing needed them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FileHashes {
+    pub partial: Option<u128>,
+    pub full: Option<u128>,
+}
+
+#[derive(Clone, Copy)]
+enum Stage {
+    Partial,
+    Full,
+}
+
+impl Stage {
+    fn get(self, hashes: &FileHashes) -> Option<u128> {
+        match self {
+            Stage::Partial => hashes.partial,
+            Stage::Full => hashes.full,
+        }
+    }
+
+    fn set(self, hashes: &mut FileHashes, digest: u128) {
+        match self {
+            Stage::Partial => hashes.partial = Some(digest),
+            Stage::Full => hashes.full = Some(digest),
+        }
+    }
+
+    fn hash(self, path: &Path, size: u64) -> std::io::Result<u128> {
+        match self {
+            Stage::Partial => hash_partial(path, size),
+            Stage::Full => hash_full(path),
+        }
+    }
+}
+
+/// Finds download-only and media-only files with identical content, i.e.
+/// copies that should have been hardlinks. Candidates are narrowed by size,
+/// then by a hash of the first and last [`PARTIAL_CHUNK`] bytes, and only the
+/// survivors are hashed in full. Hashes in `cached` are used instead of
+/// reading the file again. `progress` is called with the number of files
+/// hashed so far and the number that need hashing.
+pub fn find_duplicates(
+    nodes: &[FileNode],
+    cached: &HashMap<FileKey, FileHashes>,
+    cancel: &CancelToken,
+    progress: impl Fn(usize, usize) + Sync,
+) -> DuplicateReport {
+    let mut report = DuplicateReport::default();
+
+    let mut by_size: HashMap<u64, Vec<&FileNode>> = HashMap::new();
+    for node in nodes {
+        if node.kind == NodeKind::File
+            && node.size > 0
+            && !node.incomplete
+            && node.has_downloads != node.has_media
+        {
+            by_size.entry(node.size).or_default().push(node);
+        }
+    }
+    let candidates: Vec<&FileNode> = by_size
+        .into_values()
+        .filter(|bucket| has_both_sides(bucket))
+        .flatten()
+        .collect();
+
+    let partial = hash_all(
+        &candidates,
+        Stage::Partial,
+        cached,
+        &mut report,
+        cancel,
+        &progress,
+    );
+    let candidates: Vec<&FileNode> = bucket_by_hash(partial)
+        .into_iter()
+        .filter(|bucket| has_both_sides(bucket))
+        .flatten()
+        .collect();
+
+    let full = hash_all(
+        &candidates,
+        Stage::Full,
+        cached,
+        &mut report,
+        cancel,
+        &progress,
+    );
+    for bucket in bucket_by_hash(full) {
+        for node in &bucket {
+            let copies: Vec<FileKey> = bucket
+                .iter()
+                .filter(|other| other.has_downloads != node.has_downloads)
+                .map(|other| other.key)
+                .collect();
+            if !copies.is_empty() {
+                report.copies.insert(node.key, copies);
+            }
+        }
+    }
+
+    report
+}
+
+fn has_both_sides(bucket: &[&FileNode]) -> bool {
+    bucket.iter().any(|n| n.has_downloads) && bucket.iter().any(|n| n.has_media)
+}
+
+fn hash_all<'a>(
+    nodes: &[&'a FileNode],
+    stage: Stage,
+    cached: &HashMap<FileKey, FileHashes>,
+    report: &mut DuplicateReport,
+    cancel: &CancelToken,
+    progress: &(impl Fn(usize, usize) + Sync),
+) -> Vec<(u64, u128, &'a FileNode)> {
+    let mut hashed = Vec::new();
+    let mut todo = Vec::new();
+    for &node in nodes {
+        match cached.get(&node.key).and_then(|h| stage.get(h)) {
+            Some(digest) => hashed.push((node.size, digest, node)),
+            None => todo.push(node),
+        }
+    }
+
+    let done = std::sync::atomic::AtomicUsize::new(0);
+    let results: Vec<(&FileNode, Result<u128, ScanIssue>)> = todo
+        .par_iter()
+        .filter(|_| !cancel.is_cancelled())
+        .map(|node| {
+            let path = node.all_paths().next().expect("scanned nodes have a path");
+            let result = stage.hash(path, node.size).map_err(|e| ScanIssue {
+                path: path.clone(),
+                reason: e.to_string(),
+            });
+            let count = done.fetch_add(1, std::sync::atomic::Ordering::Relaxed) + 1;
+            progress(count, todo.len());
+            (*node, result)
+        })
+        .collect();
+
+    for (node, result) in results {
+        match result {
+            Ok(digest) => hashed.push((node.size, digest, node)),
+            Err(issue) => report.issues.push(issue),
+        }
+    }
+    for &(_, digest, node) in &hashed {
+        let hashes = report
+            .hashes
+            .entry(node.key)
+            .or_insert_with(|| cached.get(&node.key).copied().unwrap_or_default());
+        stage.set(hashes, digest);
+    }
+    hashed
+}
+
+fn bucket_by_hash(hashed: Vec<(u64, u128, &FileNode)>) -> Vec<Vec<&FileNode>> {
+    let mut buckets: HashMap<(u64, u128), Vec<&FileNode>> = HashMap::new();
+    for (size, digest, node) in hashed {
+        buckets.entry((size, digest)).or_default().push(node);
+    }
+    buckets.into_values().collect()
+}
+
+fn hash_partial(path: &Path, size: u64) -> std::io::Result<u128> {
+    let mut file = File::open(path)?;
+    let mut hasher = Xxh3::new();
+    let mut buf = vec![0; PARTIAL_CHUNK as usize];
+
+    let n = read_up_to(&mut file, &mut buf)?;
+    hasher.update(&buf[..n]);
+    if size > PARTIAL_CHUNK * 2 {
+        file.seek(SeekFrom::End(-(PARTIAL_CHUNK as i64)))?;
+        let n = read_up_to(&mut file, &mut buf)?;
+        hasher.update(&buf[..n]);
+    }
+    Ok(hasher.digest128())
+}
+
+fn hash_full(path: &Path) -> std::io::Result<u128> {
+    let mut file = File::open(path)?;
+    let mut hasher = Xxh3::new();
+    let mut buf = vec![0; 1024 * 1024];
+    loop {
+        let n = file.read(&mut buf)?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(hasher.digest128())
+}
+
+pub fn read_up_to(file: &mut File, buf: &mut [u8]) -> std::io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        let n = file.read(&mut buf[filled..])?;
+        if n == 0 {
+            break;
+        }
+        filled += n;
+    }
+    Ok(filled)
+}
@@ -0,0 +1,182 @@
+use crate::config::{Config, RootFilterConfig};
+use anyhow::Result;
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use ignore::Match;
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Name of the gitignore-style file the scanner honours inside scanned trees.
+pub const IGNORE_FILE: &str = ".ratatidyignore";
+
+/// Suffixes torrent clients add to files that are still being downloaded,
+/// e.g. `Movie.mkv.!qB`.
+const PARTIAL_EXTENSIONS: &[&str] = &["!qb", "part"];
+
+/// Whether `path` carries a partial-download suffix.
+pub fn is_partial(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| {
+        PARTIAL_EXTENSIONS.contains(&ext.to_string_lossy().to_lowercase().as_str())
+    })
+}
+
+/// Filter settings as written in the config, before compiling them per root.
+#[derive(Debug, Clone, Default)]
+pub struct FilterRules {
+    pub extensions: Vec<String>,
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+    pub root_filters: Vec<RootFilterConfig>,
+}
+
+impl FilterRules {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            extensions: config.video_extensions.clone(),
+            include: config.include.clone(),
+            exclude: config.exclude.clone(),
+            root_filters: config.root_filters.clone(),
+        }
+    }
+
+    /// Compiles the global patterns plus any configured for `root`.
+    pub fn compile(&self, root: &Path) -> Result<ScanFilter> {
+        let per_root = self.root_filters.iter().filter(|f| f.path == root);
+
+        let mut include = GlobSetBuilder::new();
+        let mut has_include = false;
+        let mut exclude = GitignoreBuilder::new(root);
+        for filter in std::iter::once((&self.include, &self.exclude))
+            .chain(per_root.map(|f| (&f.include, &f.exclude)))
+        {
+            for pattern in filter.0 {
+                include.add(Glob::new(pattern)?);
+                has_include = true;
+            }
+            for pattern in filter.1 {
+                exclude.add_line(None, pattern)?;
+            }
+        }
+
+        Ok(ScanFilter {
+            root: root.to_path_buf(),
+            extensions: self
+                .extensions
+                .iter()
+                .map(|e| e.trim_start_matches('.').to_lowercase())
+                .filter(|e| !e.is_empty())
+                .collect(),
+            include: has_include.then(|| include.build()).transpose()?,
+            ignores: IgnoreStack::root(exclude.build()?),
+        })
+    }
+}
+
+/// Compiled filters for one scan root.
+pub struct ScanFilter {
+    root: PathBuf,
+    /// Lowercase extensions to keep, empty to keep every file.
+    extensions: Vec<String>,
+    /// Globs matched against paths relative to the root.
+    include: Option<GlobSet>,
+    /// Exclude patterns from the config, the base of every ignore stack.
+    pub ignores: Arc<IgnoreStack>,
+}
+
+impl ScanFilter {
+    /// Whether `path` is excluded by the config or by a `.ratatidyignore`
+    /// between the root and it. For single paths outside a walk, where no
+    /// [`IgnoreStack`] has been built up along the way.
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let Some(parent) = path.parent() else {
+            return false;
+        };
+        let Ok(relative) = parent.strip_prefix(&self.root) else {
+            return false;
+        };
+
+        let mut stack = self.ignores.clone();
+        let mut dir = self.root.clone();
+        for component in std::iter::once(None).chain(relative.components().map(Some)) {
+            if let Some(component) = component {
+                dir.push(component);
+                if stack.is_ignored(&dir, true) {
+                    return true;
+                }
+            }
+            if dir.join(IGNORE_FILE).is_file()
+                && let Ok(layered) = IgnoreStack::push(&stack, &dir)
+            {
+                stack = layered;
+            }
+        }
+        stack.is_ignored(path, is_dir)
+    }
+
+    /// Whether a file passes the extension and include filters. Exclusions
+    /// are checked separately against the directory's [`IgnoreStack`].
+    /// Partial downloads are judged by the name they will have once done.
+    pub fn keeps_file(&self, path: &Path) -> bool {
+        let finished;
+        let path = if is_partial(path) {
+            finished = path.with_extension("");
+            finished.as_path()
+        } else {
+            path
+        };
+        if !self.extensions.is_empty() {
+            let ext = path
+                .extension()
+                .map(|e| e.to_string_lossy().to_lowercase())
+                .unwrap_or_default();
+            if !self.extensions.contains(&ext) {
+                return false;
+            }
+        }
+        match (&self.include, path.strip_prefix(&self.root)) {
+            (Some(include), Ok(relative)) => include.is_match(relative),
+            _ => true,
+        }
+    }
+}
+
+/// Exclusion rules in effect for a directory: the config excludes plus every
+/// `.ratatidyignore` between the root and the directory. Deeper files take
+/// precedence, as with nested `.gitignore` files.
+pub struct IgnoreStack {
+    parent: Option<Arc<IgnoreStack>>,
+    matcher: Gitignore,
+}
+
+impl IgnoreStack {
+    fn root(matcher: Gitignore) -> Arc<Self> {
+        Arc::new(Self {
+            parent: None,
+            matcher,
+        })
+    }
+
+    /// Adds the `.ratatidyignore` found in `dir` on top of `parent`.
+    pub fn push(parent: &Arc<Self>, dir: &Path) -> Result<Arc<Self>> {
+        let mut builder = GitignoreBuilder::new(dir);
+        if let Some(e) = builder.add(dir.join(IGNORE_FILE)) {
+            return Err(e.into());
+        }
+        Ok(Arc::new(Self {
+            parent: Some(parent.clone()),
+            matcher: builder.build()?,
+        }))
+    }
+
+    pub fn is_ignored(&self, path: &Path, is_dir: bool) -> bool {
+        let mut stack = Some(self);
+        while let Some(layer) = stack {
+            match layer.matcher.matched(path, is_dir) {
+                Match::Ignore(_) => return true,
+                Match::Whitelist(_) => return false,
+                Match::None => stack = layer.parent.as_deref(),
+            }
+        }
+        false
+    }
+}
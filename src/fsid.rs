@@ -0,0 +1,106 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Stable names for the filesystems currently mounted, keyed by device
+/// number. Device numbers are handed out at mount time and can change across
+/// reboots or remounts (NFS, mergerfs, btrfs subvolumes), so the cache
+/// remembers the filesystem behind each number instead of trusting it.
+#[derive(Debug, Default)]
+pub struct MountTable {
+    by_dev: HashMap<u64, String>,
+}
+
+impl MountTable {
+    /// Reads `/proc/self/mountinfo`, naming each filesystem by its UUID where
+    /// `/dev/disk/by-uuid` has one and by its mount source otherwise. Empty on
+    /// other platforms, where cached devices are only trusted while the root's
+    /// device number stays the same.
+    pub fn load() -> Self {
+        let Ok(mountinfo) = std::fs::read_to_string("/proc/self/mountinfo") else {
+            return Self::default();
+        };
+        let uuids = disk_uuids();
+
+        let mut by_dev: HashMap<u64, String> = HashMap::new();
+        for line in mountinfo.lines() {
+            if let Some((dev, identity)) = parse_mountinfo_line(line, &uuids) {
+                // Bind mounts repeat a device; the first entry is the real mount.
+                by_dev.entry(dev).or_insert(identity);
+            }
+        }
+
+        // A name shared by several devices identifies none of them.
+        let mut uses: HashMap<String, usize> = HashMap::new();
+        for identity in by_dev.values() {
+            *uses.entry(identity.clone()).or_default() += 1;
+        }
+        by_dev.retain(|_, identity| uses[identity.as_str()] == 1);
+        Self { by_dev }
+    }
+
+    pub fn identity(&self, dev: u64) -> Option<&str> {
+        self.by_dev.get(&dev).map(String::as_str)
+    }
+
+    /// Current device number of the filesystem named `identity`, if mounted.
+    pub fn device(&self, identity: &str) -> Option<u64> {
+        self.by_dev
+            .iter()
+            .find(|(_, id)| id.as_str() == identity)
+            .map(|(dev, _)| *dev)
+    }
+}
+
+/// Maps resolved block device paths (`/dev/sda1`) to their filesystem UUID.
+fn disk_uuids() -> HashMap<String, String> {
+    let Ok(entries) = std::fs::read_dir("/dev/disk/by-uuid") else {
+        return HashMap::new();
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let device = std::fs::canonicalize(entry.path()).ok()?;
+            let uuid = entry.file_name().into_string().ok()?;
+            Some((device.to_string_lossy().into_owned(), uuid))
+        })
+        .collect()
+}
+
+/// Parses one mountinfo line:
+/// `36 35 98:0 /mnt1 /mnt/parent rw,noatime master:1 - ext3 /dev/root rw`.
+/// Btrfs subvolumes share a UUID but get their own device, so the path of
+/// the mounted subvolume is part of their identity. Filesystems without a
+/// UUID (NFS, FUSE, tmpfs) are named by their source and mount point.
+fn parse_mountinfo_line(line: &str, uuids: &HashMap<String, String>) -> Option<(u64, String)> {
+    let (mount, fs) = line.split_once(" - ")?;
+    let mut fields = mount.split(' ');
+    let (major, minor) = fields.nth(2)?.split_once(':')?;
+    let fs_root = fields.next()?;
+    let mount_point = fields.next()?;
+    let mut fs = fs.split(' ');
+    let fstype = fs.next()?;
+    let source = fs.next()?;
+
+    let dev = makedev(major.parse().ok()?, minor.parse().ok()?);
+    let name = Some(Path::new(source))
+        .filter(|source| source.is_absolute())
+        .and_then(|source| source.canonicalize().ok())
+        .and_then(|device| uuids.get(device.to_string_lossy().as_ref()))
+        .map(|uuid| format!("uuid={}", uuid))
+        .unwrap_or_else(|| format!("source={}@{}", source, mount_point));
+    let identity = if fstype == "btrfs" {
+        format!("{}:{}:{}", fstype, name, fs_root)
+    } else {
+        format!("{}:{}", fstype, name)
+    };
+    Some((dev, identity))
+}
+
+/// Packs a major/minor pair the way glibc's `makedev` does, matching
+/// `MetadataExt::dev`.
+fn makedev(major: u64, minor: u64) -> u64 {
+    ((major & 0xffff_f000) << 32)
+        | ((major & 0x0000_0fff) << 8)
+        | ((minor & 0xffff_ff00) << 12)
+        | (minor & 0x0000_00ff)
+}
@@ -1,13 +1,17 @@
-use crate::scanner::FileNode;
-use std::collections::HashMap;
-use std::path::{Path, PathBuf};
+use crate::config::{GroupingConfig, MediaKindConfig};
+use crate::release::{self, Release, is_video};
+use crate::scanner::{FileKey, FileNode};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::ffi::OsStr;
+use std::path::{Component, Path, PathBuf};
+use std::rc::Rc;
 
-#[allow(dead_code)]
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum GroupKind {
     Movie,
     Series,
-    DownloadTorrent,
     Other,
 }
 
@@ -15,90 +19,530 @@ pub enum GroupKind {
 #[derive(Debug, Clone)]
 pub struct Group {
     pub kind: GroupKind,
+    /// Parsed title, or the folder name when it doesn't parse to one.
     pub title: String,
-    pub media_container: Option<PathBuf>,
-    pub downloads_container: Option<PathBuf>,
-    pub nodes: Vec<FileNode>,
-}
-
-pub fn group_by_media(nodes: &[FileNode], media_dirs: &[PathBuf]) -> Vec<Group> {
-    let mut groups: HashMap<PathBuf, Vec<FileNode>> = HashMap::new();
-
-    for node in nodes {
-        if node.has_media {
-            for path in &node.paths {
-                // Find which media dir this path belongs to
-                for media_dir in media_dirs {
-                    if path.starts_with(media_dir) {
-                        // Heuristic: Group by the first folder inside the media_dir
-                        // e.g. /media/movies/Inception (2010)/Inception.mkv -> Inception (2010)
-                        if let Ok(relative) = path.strip_prefix(media_dir) {
-                            let components: Vec<_> = relative.components().collect();
-                            if !components.is_empty() {
-                                let group_folder = media_dir.join(components[0].as_os_str());
-                                groups.entry(group_folder).or_default().push(node.clone());
-                            }
-                        }
-                        break;
-                    }
+    pub release: Release,
+    /// Shared with [`ReleaseCache`], which hands out one allocation per
+    /// container.
+    pub media_container: Option<Rc<Path>>,
+    pub downloads_container: Option<Rc<Path>>,
+    /// Indices into the node list the group was built from, each node once.
+    /// Only valid until that list changes; regroup after every change.
+    pub nodes: Vec<usize>,
+    /// Indices of the groups in the other view sharing a file with this
+    /// one: download groups for a media group, media groups for a download
+    /// group. Filled in by [`link_related`].
+    pub related: Vec<usize>,
+}
+
+impl Group {
+    /// Whether the title, a container's name or any parsed release field
+    /// contains `query`, which must be lowercase.
+    pub fn matches(&self, query: &str) -> bool {
+        self.title.to_lowercase().contains(query)
+            || [&self.media_container, &self.downloads_container]
+                .into_iter()
+                .flatten()
+                .filter_map(|path| path.file_name())
+                .any(|name| name.to_string_lossy().to_lowercase().contains(query))
+            || self.release.matches(query)
+    }
+
+    /// The folder, or single file, the group was built from.
+    pub fn container(&self) -> Option<&Path> {
+        self.media_container
+            .as_deref()
+            .or(self.downloads_container.as_deref())
+    }
+
+    /// The group's nodes, looked up in the list it was built from.
+    pub fn files<'a>(&'a self, nodes: &'a [FileNode]) -> impl Iterator<Item = &'a FileNode> + 'a {
+        self.nodes.iter().map(move |&i| &nodes[i])
+    }
+}
+
+/// Decides which folder, or single file, each file below a root belongs to.
+/// Chosen per root with [`GroupingConfig`]; roots without one use
+/// [`Depth`]`(1)`.
+pub trait GroupingStrategy {
+    /// Splits the files below `root` into groups by container. `paths` pairs
+    /// a node index with one of its paths below `root`, a node's paths next
+    /// to each other.
+    fn group<'a>(
+        &self,
+        nodes: &[FileNode],
+        root: &Path,
+        paths: &[(usize, &'a Path)],
+    ) -> HashMap<&'a Path, Vec<usize>>;
+}
+
+/// Built-in strategies, as named in the config file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StrategyKind {
+    Depth,
+    Release,
+    Torrent,
+}
+
+impl GroupingConfig {
+    pub fn strategy(&self) -> Box<dyn GroupingStrategy> {
+        match self.strategy {
+            StrategyKind::Depth => Box::new(Depth(self.depth)),
+            StrategyKind::Release => Box::new(ReleaseName),
+            StrategyKind::Torrent => Box::new(Torrent),
+        }
+    }
+}
+
+/// Adds node `i` to the group at `container`. Several links of one file in
+/// the same group count once; a node's paths are visited together, so
+/// checking the last is enough.
+fn add<'a>(groups: &mut HashMap<&'a Path, Vec<usize>>, container: &'a Path, i: usize) {
+    let group = groups.entry(container).or_default();
+    if group.last() != Some(&i) {
+        group.push(i);
+    }
+}
+
+/// Groups by the folder `n` levels below the root, e.g. with 2
+/// `TV/Network/Show/Season 01/e1.mkv` -> `Network/Show`. Files less deep
+/// are groups of their own.
+pub struct Depth(pub usize);
+
+impl GroupingStrategy for Depth {
+    fn group<'a>(
+        &self,
+        _nodes: &[FileNode],
+        root: &Path,
+        paths: &[(usize, &'a Path)],
+    ) -> HashMap<&'a Path, Vec<usize>> {
+        let mut groups = HashMap::new();
+        for &(i, path) in paths {
+            // Borrowed from the path, so nothing is allocated per file
+            let container = path
+                .ancestors()
+                .find(|a| {
+                    a.strip_prefix(root)
+                        .is_ok_and(|r| r.components().count() <= self.0.max(1))
+                })
+                .unwrap_or(path);
+            add(&mut groups, container, i);
+        }
+        groups
+    }
+}
+
+/// Groups by the outermost folder named like a release, skipping layout
+/// folders such as `A-Z`, a network name or a bare tag like `4K`:
+/// `Movies/4K/Inception (2010)` or,
+/// for a bare `Season 01` or episode, the folder holding it. Files below no
+/// such name are grouped by the folder they are in.
+pub struct ReleaseName;
+
+impl GroupingStrategy for ReleaseName {
+    fn group<'a>(
+        &self,
+        _nodes: &[FileNode],
+        root: &Path,
+        paths: &[(usize, &'a Path)],
+    ) -> HashMap<&'a Path, Vec<usize>> {
+        let mut groups = HashMap::new();
+        for &(i, path) in paths {
+            // Root's first folder down to the file itself
+            let mut chain: Vec<&Path> = path.ancestors().take_while(|a| *a != root).collect();
+            chain.reverse();
+            let named = chain.iter().enumerate().find_map(|(depth, dir)| {
+                let name = dir.file_name()?.to_str()?;
+                let release = release::parse(name);
+                if !release.is_tagged() {
+                    return None;
+                }
+                // `Season 01` or a bare episode belongs to the folder above
+                let bare = release.is_episodic() && (release.title.is_empty() || *dir == path);
+                if bare {
+                    return Some(if depth > 0 { chain[depth - 1] } else { dir });
                 }
+                // A tag without a title, like `2160p` or `Remux`, is layout
+                (!release.title.is_empty()).then_some(*dir)
+            });
+            let container = named
+                .or_else(|| chain.iter().rev().nth(1).copied())
+                .unwrap_or(path);
+            add(&mut groups, container, i);
+        }
+        groups
+    }
+}
+
+/// Groups the files of one torrent together, under the deepest folder they
+/// share. A single-file torrent is its own group, as the folder it sits in
+/// is usually a category shared with other torrents. Files of no torrent,
+/// or of one spread over the root itself, are grouped by [`Depth`]`(1)`.
+pub struct Torrent;
+
+impl GroupingStrategy for Torrent {
+    fn group<'a>(
+        &self,
+        nodes: &[FileNode],
+        root: &Path,
+        paths: &[(usize, &'a Path)],
+    ) -> HashMap<&'a Path, Vec<usize>> {
+        let mut torrents: HashMap<&str, Vec<(usize, &'a Path)>> = HashMap::new();
+        let mut rest = Vec::new();
+        for &(i, path) in paths {
+            match &nodes[i].torrent_hash {
+                Some(hash) => torrents.entry(hash).or_default().push((i, path)),
+                None => rest.push((i, path)),
+            }
+        }
+
+        let mut groups = HashMap::new();
+        for files in torrents.into_values() {
+            if files.iter().all(|&(i, _)| i == files[0].0) {
+                files
+                    .iter()
+                    .for_each(|&(i, path)| add(&mut groups, path, i));
+                continue;
+            }
+            let shared =
+                files
+                    .iter()
+                    .filter_map(|(_, path)| path.parent())
+                    .reduce(|shared, dir| {
+                        shared
+                            .ancestors()
+                            .find(|a| dir.starts_with(a))
+                            .unwrap_or(shared)
+                    });
+            match shared.filter(|dir| *dir != root && dir.starts_with(root)) {
+                Some(dir) => files.iter().for_each(|&(i, _)| add(&mut groups, dir, i)),
+                None => rest.extend(files),
+            }
+        }
+        for (container, members) in Depth(1).group(nodes, root, &rest) {
+            groups.entry(container).or_default().extend(members);
+        }
+        groups
+    }
+}
+
+/// Collects node indices by container below each of `roots`, using the
+/// root's configured strategy.
+fn collect<'a>(
+    nodes: &'a [FileNode],
+    roots: &[PathBuf],
+    grouping: &[GroupingConfig],
+    include: impl Fn(&FileNode) -> bool,
+) -> Vec<(usize, &'a Path, Vec<usize>)> {
+    let mut below: Vec<Vec<(usize, &Path)>> = vec![Vec::new(); roots.len()];
+    for (i, node) in nodes.iter().enumerate().filter(|(_, n)| include(n)) {
+        for path in node.all_paths() {
+            if let Some(r) = roots
+                .iter()
+                .position(|root| path.starts_with(root) && path != root)
+            {
+                below[r].push((i, path));
             }
         }
     }
 
+    let mut groups = Vec::new();
+    for (r, (root, paths)) in roots.iter().zip(below).enumerate() {
+        let strategy = grouping
+            .iter()
+            .find(|g| g.path == *root)
+            .map(GroupingConfig::strategy)
+            .unwrap_or_else(|| Box::new(Depth(1)));
+        for (container, members) in strategy.group(nodes, root, &paths) {
+            groups.push((r, container, members));
+        }
+    }
     groups
-        .into_iter()
-        .map(|(path, nodes)| {
-            let title = path
+}
+
+/// Title, release and detected kind of each container, kept across
+/// regroups so a watcher batch only parses the names of the containers it
+/// touched again. Also interns the container paths: groups and list rows
+/// share the cache's allocation, and a regroup reuses it for every
+/// container it finds again.
+#[derive(Debug, Default)]
+pub struct ReleaseCache(HashMap<Rc<Path>, (String, Release, GroupKind)>);
+
+impl ReleaseCache {
+    /// Forgets every container holding one of `paths`, or below one of them
+    /// when it was a folder.
+    pub fn forget<'a>(&mut self, paths: impl IntoIterator<Item = &'a Path>) {
+        let paths: HashSet<&Path> = paths.into_iter().collect();
+        if paths.is_empty() {
+            return;
+        }
+        for path in &paths {
+            for ancestor in path.ancestors() {
+                self.0.remove(ancestor);
+            }
+        }
+        self.0
+            .retain(|container, _| !container.ancestors().any(|a| paths.contains(a)));
+    }
+
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
+
+    /// The interned container path with its title, release and kind.
+    fn get(
+        &mut self,
+        nodes: &[FileNode],
+        members: &[usize],
+        container: &Path,
+    ) -> (Rc<Path>, String, Release, GroupKind) {
+        if let Some((path, (title, release, kind))) = self.0.get_key_value(container) {
+            return (path.clone(), title.clone(), release.clone(), *kind);
+        }
+        let (title, release) = parse_release(nodes, members, container);
+        let kind = classify(nodes, members, container);
+        let path: Rc<Path> = container.into();
+        self.0
+            .insert(path.clone(), (title.clone(), release.clone(), kind));
+        (path, title, release, kind)
+    }
+}
+
+/// Parses the container's name, taking what it lacks from the group's
+/// first video file that has it.
+fn parse_release(nodes: &[FileNode], members: &[usize], container: &Path) -> (String, Release) {
+    let name = container.file_name().unwrap_or_default().to_string_lossy();
+    let mut release = release::parse(&name);
+    for path in members.iter().flat_map(|&i| nodes[i].all_paths()) {
+        if path.starts_with(container) && path != container && is_video(path) {
+            release.fill_missing(&release::parse(
+                &path.file_name().unwrap_or_default().to_string_lossy(),
+            ));
+        }
+    }
+    let title = if release.title.is_empty() {
+        name.to_string()
+    } else {
+        release.title.clone()
+    };
+    (title, release)
+}
+
+/// Series when the folder or anything below it is named after a season or
+/// episode, movie when it holds any other video, otherwise something else.
+fn classify(nodes: &[FileNode], members: &[usize], container: &Path) -> GroupKind {
+    let mut has_video = false;
+    for &i in members {
+        for path in nodes[i].all_paths() {
+            let Ok(relative) = path.strip_prefix(container) else {
+                continue;
+            };
+            let names = container
                 .file_name()
-                .unwrap_or_default()
-                .to_string_lossy()
-                .to_string();
+                .into_iter()
+                .chain(relative.components().filter_map(|c| match c {
+                    Component::Normal(name) => Some(name),
+                    _ => None,
+                }));
+            if names
+                .filter_map(OsStr::to_str)
+                .any(|name| release::parse(name).is_episodic())
+            {
+                return GroupKind::Series;
+            }
+            has_video |= is_video(path);
+        }
+    }
+    if has_video {
+        GroupKind::Movie
+    } else {
+        GroupKind::Other
+    }
+}
+
+/// Groups media files by container, found with each folder's strategy.
+/// `media_kinds` overrides the detected kind for every group below a
+/// configured folder.
+pub fn group_by_media(
+    nodes: &[FileNode],
+    releases: &mut ReleaseCache,
+    media_dirs: &[PathBuf],
+    media_kinds: &[MediaKindConfig],
+    grouping: &[GroupingConfig],
+) -> Vec<Group> {
+    collect(nodes, media_dirs, grouping, |n| n.has_media)
+        .into_iter()
+        .map(|(root, path, members)| {
+            let hint = media_kinds
+                .iter()
+                .find(|k| k.path == media_dirs[root])
+                .map(|k| k.kind);
+            let (path, title, release, kind) = releases.get(nodes, &members, path);
             Group {
-                kind: GroupKind::Movie, // Simplify for now, could detect Series if it has subfolders
+                kind: hint.unwrap_or(kind),
                 title,
+                release,
                 media_container: Some(path),
                 downloads_container: None,
-                nodes,
+                nodes: members,
+                related: Vec::new(),
             }
         })
         .collect()
 }
 
-pub fn group_by_downloads(nodes: &[FileNode], download_dir: &Path) -> Vec<Group> {
-    let mut groups: HashMap<PathBuf, Vec<FileNode>> = HashMap::new();
-
-    for node in nodes {
-        if node.has_downloads {
-            for path in &node.paths {
-                if path.starts_with(download_dir) {
-                    if let Ok(relative) = path.strip_prefix(download_dir) {
-                        let components: Vec<_> = relative.components().collect();
-                        if !components.is_empty() {
-                            let group_item = download_dir.join(components[0].as_os_str());
-                            groups.entry(group_item).or_default().push(node.clone());
-                        }
-                    }
-                }
-            }
-        }
-    }
-
-    groups
+pub fn group_by_downloads(
+    nodes: &[FileNode],
+    releases: &mut ReleaseCache,
+    download_dirs: &[PathBuf],
+    grouping: &[GroupingConfig],
+) -> Vec<Group> {
+    collect(nodes, download_dirs, grouping, |n| n.has_downloads)
         .into_iter()
-        .map(|(path, nodes)| {
-            let title = path
-                .file_name()
-                .unwrap_or_default()
-                .to_string_lossy()
-                .to_string();
+        .map(|(_, path, members)| {
+            let (path, title, release, kind) = releases.get(nodes, &members, path);
             Group {
-                kind: GroupKind::DownloadTorrent,
+                kind,
                 title,
+                release,
                 media_container: None,
                 downloads_container: Some(path),
-                nodes,
+                nodes: members,
+                related: Vec::new(),
             }
         })
         .collect()
 }
+
+/// Connects media and download groups that share a file, e.g. a movie and
+/// the torrent folder it was hardlinked from.
+pub fn link_related(nodes: &[FileNode], media: &mut [Group], downloads: &mut [Group]) {
+    let mut owners: HashMap<FileKey, Vec<usize>> = HashMap::new();
+    for (m, group) in media.iter().enumerate() {
+        for node in group.files(nodes) {
+            owners.entry(node.key).or_default().push(m);
+        }
+    }
+    let mut links: BTreeSet<(usize, usize)> = BTreeSet::new();
+    for (d, group) in downloads.iter().enumerate() {
+        for node in group.files(nodes) {
+            for &m in owners.get(&node.key).into_iter().flatten() {
+                links.insert((m, d));
+            }
+        }
+    }
+    for (m, d) in links {
+        media[m].related.push(d);
+        downloads[d].related.push(m);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn containers(root: &str, files: &[&str]) -> Vec<(PathBuf, Vec<usize>)> {
+        let paths: Vec<PathBuf> = files.iter().map(PathBuf::from).collect();
+        let pairs: Vec<(usize, &Path)> = paths
+            .iter()
+            .enumerate()
+            .map(|(i, p)| (i, p.as_path()))
+            .collect();
+        let mut groups: Vec<(PathBuf, Vec<usize>)> = ReleaseName
+            .group(&[], Path::new(root), &pairs)
+            .into_iter()
+            .map(|(container, members)| (container.to_path_buf(), members))
+            .collect();
+        groups.sort();
+        groups
+    }
+
+    fn torrent_file(hash: &str, path: &str) -> FileNode {
+        FileNode {
+            key: FileKey { dev: 0, inode: 0 },
+            size: 0,
+            nlink: 1,
+            paths: vec![PathBuf::from(path)],
+            has_downloads: true,
+            has_media: false,
+            torrent_hash: Some(hash.to_string()),
+            is_seeding: false,
+            modified: None,
+            kind: Default::default(),
+            duplicates: Vec::new(),
+            incomplete: false,
+            symlinks: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn torrent_keeps_single_file_torrents_in_one_folder_apart() {
+        let nodes = [
+            torrent_file("a", "/Downloads/movies/Heat.1995.mkv"),
+            torrent_file("b", "/Downloads/movies/Ronin.1998.mkv"),
+            torrent_file("c", "/Downloads/movies/Alien.1979/Alien.1979.mkv"),
+            torrent_file("c", "/Downloads/movies/Alien.1979/Subs/eng.srt"),
+        ];
+        let pairs: Vec<(usize, &Path)> = nodes
+            .iter()
+            .enumerate()
+            .map(|(i, n)| (i, n.paths[0].as_path()))
+            .collect();
+        let mut groups: Vec<(PathBuf, Vec<usize>)> = Torrent
+            .group(&nodes, Path::new("/Downloads"), &pairs)
+            .into_iter()
+            .map(|(container, mut members)| {
+                members.sort();
+                (container.to_path_buf(), members)
+            })
+            .collect();
+        groups.sort();
+        assert_eq!(
+            groups,
+            [
+                (PathBuf::from("/Downloads/movies/Alien.1979"), vec![2, 3]),
+                (PathBuf::from("/Downloads/movies/Heat.1995.mkv"), vec![0]),
+                (PathBuf::from("/Downloads/movies/Ronin.1998.mkv"), vec![1]),
+            ]
+        );
+    }
+
+    #[test]
+    fn release_name_skips_tag_only_layout_folders() {
+        let groups = containers(
+            "/Movies",
+            &[
+                "/Movies/4K/Inception (2010)/Inception.2010.2160p.mkv",
+                "/Movies/4K/Inception (2010)/Subs/eng.srt",
+                "/Movies/Remux/Heat (1995)/Heat.1995.mkv",
+            ],
+        );
+        assert_eq!(
+            groups,
+            [
+                (PathBuf::from("/Movies/4K/Inception (2010)"), vec![0, 1]),
+                (PathBuf::from("/Movies/Remux/Heat (1995)"), vec![2]),
+            ]
+        );
+    }
+
+    #[test]
+    fn release_name_groups_seasons_and_episodes_by_show() {
+        let groups = containers(
+            "/TV",
+            &[
+                "/TV/The Bear/Season 01/The.Bear.S01E01.mkv",
+                "/TV/The Bear/Season 02/The.Bear.S02E01.mkv",
+                "/TV/Frieren/[SubsPlease] Frieren - 05 (1080p).mkv",
+            ],
+        );
+        assert_eq!(
+            groups,
+            [
+                (PathBuf::from("/TV/Frieren"), vec![2]),
+                (PathBuf::from("/TV/The Bear"), vec![0, 1]),
+            ]
+        );
+    }
+}
@@ -1,17 +1,29 @@
 mod app;
+mod cache;
 mod config;
+mod duplicates;
+mod filters;
+mod fsid;
 mod grouping;
 mod qbittorrent;
+mod release;
+mod relink;
 mod scanner;
+mod snapshot;
+mod source;
 mod tui;
 mod ui;
+mod watcher;
 
-use crate::app::{App, AppState};
-use crate::config::Config;
+use crate::app::{App, WatchStatus};
+use crate::config::{Command, Config, DownloadDir};
+use crate::filters::FilterRules;
 use crate::qbittorrent::{MockQbitClient, QbitClient, RealQbitClient};
-use crate::scanner::Scanner;
+use crate::scanner::{ScanEvent, Scanner};
+use crate::source::{ExportedScan, FileSource, SnapshotFile};
 use crate::tui::Tui;
-use anyhow::Result;
+use crate::watcher::WatchEvent;
+use anyhow::{Result, bail};
 use crossterm::event::{self, Event, KeyCode, KeyEventKind};
 use rustyline::completion::{Completer, FilenameCompleter, Pair};
 use rustyline::highlight::Highlighter;
@@ -19,7 +31,7 @@ use rustyline::hint::Hinter;
 use rustyline::validate::Validator;
 use rustyline::{CompletionType, Config as RlConfig, Context, Editor, Helper};
 use std::path::{Path, PathBuf};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 // Custom helper for path completion
 struct FilePathHelper {
@@ -141,34 +153,139 @@ fn prompt_paths(message: &str) -> Result<Vec<PathBuf>> {
     Ok(paths)
 }
 
+fn build_scanner(config: &Config) -> Scanner {
+    Scanner::new(config.download_paths(), config.media_dirs.clone())
+        .with_threads(config.scan_threads)
+        .with_filters(FilterRules::from_config(config))
+        .with_symlinks(config.symlinks)
+        .with_duplicate_detection(config.detect_duplicates)
+        .with_one_file_system(config.one_file_system)
+        .with_snapshots(config.snapshots)
+}
+
+fn start_scan(source: &dyn FileSource, app: &mut App) {
+    let (tx, rx) = std::sync::mpsc::channel();
+    let cancel = source.load(tx);
+    app.begin_scan(rx, cancel);
+}
+
+/// Runs one scan without the TUI and prints a summary, optionally exporting
+/// the nodes so the TUI can browse them on another machine.
+fn run_scan(scanner: &Scanner, export: Option<&Path>) -> Result<()> {
+    let (tx, rx) = std::sync::mpsc::channel();
+    scanner.scan_async(tx);
+    for event in rx {
+        match event {
+            ScanEvent::Progress(_) => {}
+            ScanEvent::Finished(nodes, report) => {
+                let count = |filter: &dyn Fn(&scanner::FileNode) -> bool| {
+                    let matching = nodes.iter().filter(|n| filter(n));
+                    let (files, size) = matching.fold((0, 0), |(f, s), n| (f + 1, s + n.size));
+                    format!("{} files ({})", files, ui::format_size(size))
+                };
+                println!("Scanned {}", count(&|_| true));
+                println!(
+                    "  hardlinked:     {}",
+                    count(&|n| n.has_downloads && n.has_media)
+                );
+                println!(
+                    "  downloads only: {}",
+                    count(&|n| n.has_downloads && !n.has_media)
+                );
+                println!(
+                    "  media only:     {}",
+                    count(&|n| !n.has_downloads && n.has_media)
+                );
+                println!("  copies:         {}", count(&|n| !n.duplicates.is_empty()));
+                if !report.issues.is_empty() {
+                    println!("  skipped paths:  {}", report.issues.len());
+                }
+                if let Some(path) = export {
+                    ExportedScan::new(nodes, report).write(path)?;
+                    println!("Exported to {}", path.display());
+                }
+                return Ok(());
+            }
+            ScanEvent::Cancelled => bail!("scan cancelled"),
+            ScanEvent::Error(e) => bail!("scan failed: {}", e),
+        }
+    }
+    bail!("scan stopped without a result")
+}
+
 #[tokio::main]
 async fn main() -> Result<()> {
     // Load .env file if it exists
     dotenvy::dotenv().ok();
 
     // Try to load saved config first, fall back to CLI/env
-    let mut config = Config::load_from_file().unwrap_or_else(Config::load);
+    let cli = Config::load();
+    let command = cli.command.clone();
+    let import = cli.import.clone();
+    let mut config = Config::load_from_file().unwrap_or(cli);
+    config.import = import;
+
+    if let Some(Command::Cache { action }) = &command {
+        return cache::run(action, &config);
+    }
     let mut config_was_prompted = false;
 
     // Development helper: If no dirs provided and mock_env exists, use it
-    if config.download_dir.is_none()
+    if config.download_dirs.is_empty()
         && config.media_dirs.is_empty()
         && Path::new("mock_env").exists()
     {
-        config.download_dir = Some(std::fs::canonicalize("mock_env/downloads")?);
+        config.download_dirs = vec![DownloadDir::new(std::fs::canonicalize(
+            "mock_env/downloads",
+        )?)];
         config.media_dirs = vec![
             std::fs::canonicalize("mock_env/media/movies")?,
             std::fs::canonicalize("mock_env/media/tvshows")?,
         ];
     }
 
-    // Interactive setup if paths are missing
-    if config.download_dir.is_none() {
+    // An exported scan brings its own roots, which only exist on the machine
+    // that scanned them
+    if let Some(path) = &config.import {
+        let (download_dirs, media_dirs) = ExportedScan::read(path)?.roots();
+        if download_dirs.is_empty() {
+            bail!("{} has no download roots", path.display());
+        }
+        // Keep the labels of roots that are configured here too
+        let labels = std::mem::take(&mut config.download_dirs);
+        config.download_dirs = download_dirs
+            .into_iter()
+            .map(|path| {
+                let label = labels.iter().find(|d| d.path == path);
+                DownloadDir {
+                    label: label.and_then(|d| d.label.clone()),
+                    path,
+                }
+            })
+            .collect();
+        config.media_dirs = media_dirs;
+    }
+
+    if let Some(Command::Scan { export }) = &command {
+        if config.import.is_some() {
+            bail!("--import opens an exported scan read-only; there is nothing to scan");
+        }
+        if config.download_dirs.is_empty() {
+            bail!("no download directory configured, pass --download-dir");
+        }
+        return run_scan(&build_scanner(&config), export.as_deref());
+    }
+
+    // Interactive setup if paths are missing. An import brings its own roots
+    // and must never end up in this machine's config file
+    if config.import.is_none() && config.download_dirs.is_empty() {
         println!("Welcome to Ratatidy! Let's set up your paths.\n");
-        config.download_dir = Some(prompt_path("Enter your download directory")?);
+        config.download_dirs = vec![DownloadDir::new(prompt_path(
+            "Enter your download directory",
+        )?)];
         config_was_prompted = true;
     }
-    if config.media_dirs.is_empty() {
+    if config.import.is_none() && config.media_dirs.is_empty() {
         config.media_dirs =
             prompt_paths("Enter your media directories (comma separated, or press Enter to skip)")?;
         config_was_prompted = true;
@@ -179,8 +296,6 @@ async fn main() -> Result<()> {
         config.save()?;
     }
 
-    let download_dir = config.download_dir.clone().unwrap();
-
     // Phase 7: Fetch qBittorrent data (optional)
     let qbit: Box<dyn QbitClient> = if Path::new("mock_env").exists() {
         Box::new(MockQbitClient)
@@ -199,17 +314,46 @@ async fn main() -> Result<()> {
         Box::new(MockQbitClient) // No credentials configured
     };
     let torrents = qbit.get_torrents().await.unwrap_or_default();
+    let incomplete_dir = match &config.incomplete_dir {
+        Some(dir) => Some(dir.clone()),
+        None => qbit.get_incomplete_dir().await.ok().flatten(),
+    };
 
-    let scanner = Scanner::new(download_dir.clone(), config.media_dirs.clone());
+    let source: Box<dyn FileSource> = match &config.import {
+        Some(path) => Box::new(SnapshotFile::new(path.clone())),
+        None => Box::new(build_scanner(&config)),
+    };
+    let mut roots = config.download_paths();
+    roots.extend(config.media_dirs.iter().cloned());
+    let rules = FilterRules::from_config(&config);
+    let poll_every = Duration::from_secs(config.rescan_interval);
     let mut app = App::new(config, Vec::new(), torrents);
+    app.incomplete_dir = incomplete_dir;
+    if !source.is_local() {
+        app.remote_source = Some(source.describe());
+    }
+
+    // Live updates, falling back to periodic rescans if inotify is unavailable
+    let mut watcher = None;
+    if app.config.watch && source.is_local() {
+        match watcher::watch(roots, &rules) {
+            Ok(w) => {
+                watcher = Some(w);
+                app.watch = WatchStatus::Live;
+            }
+            Err(e) => {
+                app.watch = WatchStatus::Polling {
+                    every: poll_every,
+                    reason: format!("{:#}", e),
+                }
+            }
+        }
+    }
+    let mut last_scan = Instant::now();
+    let mut rescan_due = false;
 
     // Initial async scan
-    let (tx, rx) = std::sync::mpsc::channel();
-    scanner.scan_async(tx);
-    app.state = AppState::Scanning {
-        processed: 0,
-        receiver: rx,
-    };
+    start_scan(source.as_ref(), &mut app);
 
     let mut tui = Tui::new()?;
     tui.init()?;
@@ -217,101 +361,132 @@ async fn main() -> Result<()> {
     while app.running {
         tui.terminal.draw(|f| ui::render(&mut app, f))?;
 
-        if event::poll(Duration::from_millis(16))? {
-            if let Event::Key(key) = event::read()? {
-                if key.kind == KeyEventKind::Press {
-                    match key.code {
-                        KeyCode::Esc => {
-                            if app.show_confirmation {
-                                app.cancel_delete();
-                            } else if app.search_active {
-                                app.search_active = false;
-                                app.search_query.clear();
-                            }
-                        }
-                        KeyCode::Char(c) if app.search_active => {
-                            app.search_query.push(c);
-                            app.selected_index = 0;
-                        }
-                        KeyCode::Backspace if app.search_active => {
-                            app.search_query.pop();
-                            app.selected_index = 0;
-                        }
-                        KeyCode::Char(c)
-                            if !app.search_active
-                                && !app.show_confirmation
-                                && c.is_ascii_digit()
-                                && c != '0' =>
-                        {
-                            if let Some(digit) = c.to_digit(10) {
-                                app.set_tab_index((digit - 1) as usize);
-                            }
-                        }
-                        KeyCode::Char('q') => app.quit(),
-                        KeyCode::Tab => app.next_tab(),
-                        KeyCode::Down | KeyCode::Char('j') => {
-                            if app.show_confirmation {
-                                app.delete_scope = app.delete_scope.next(&app.available_scopes);
-                            } else {
-                                app.select_next();
-                            }
-                        }
-                        KeyCode::Up | KeyCode::Char('k') => {
-                            if app.show_confirmation {
-                                app.delete_scope = app.delete_scope.prev(&app.available_scopes);
-                            } else {
-                                app.select_prev();
-                            }
-                        }
-                        KeyCode::Char('i') => {
-                            if !app.show_confirmation {
-                                app.toggle_details();
-                            }
-                        }
-                        KeyCode::Char('t') | KeyCode::Char('d') => {
-                            if !app.show_confirmation {
-                                app.request_delete();
-                            }
-                        }
-                        KeyCode::Char('f') => {
-                            if !app.show_confirmation && !app.search_active {
-                                app.filter = app.filter.next();
-                            }
-                        }
-                        KeyCode::Char('s') => {
-                            if !app.show_confirmation && !app.search_active {
-                                app.sort_by = app.sort_by.next();
-                            }
-                        }
-                        KeyCode::Char('S') => {
-                            if !app.show_confirmation && !app.search_active {
-                                app.sort_order = app.sort_order.toggle();
-                            }
-                        }
-                        KeyCode::Char('/') => {
-                            if !app.show_confirmation {
-                                app.search_active = true;
-                            }
-                        }
-                        KeyCode::Char('r') => {
-                            if !app.show_confirmation && !app.search_active {
-                                // Rescan logic
-                                let (tx, rx) = std::sync::mpsc::channel();
-                                scanner.scan_async(tx);
-                                app.state = AppState::Scanning {
-                                    processed: 0,
-                                    receiver: rx,
-                                };
-                            }
-                        }
-                        KeyCode::Enter => {
-                            if app.show_confirmation {
-                                app.confirm_delete();
-                            }
-                        }
-                        _ => {}
+        if event::poll(Duration::from_millis(16))?
+            && let Event::Key(key) = event::read()?
+            && key.kind == KeyEventKind::Press
+        {
+            app.status_message = None;
+            match key.code {
+                KeyCode::Esc => {
+                    if app.show_confirmation {
+                        app.cancel_delete();
+                    } else if app.show_relink {
+                        app.cancel_relink();
+                    } else if app.show_warnings {
+                        app.show_warnings = false;
+                    } else if app.changes.is_some() {
+                        app.changes = None;
+                    } else if app.search_active {
+                        app.search_active = false;
+                        app.search_query.clear();
+                    } else if app.is_scanning() {
+                        app.cancel_scan();
+                    }
+                }
+                KeyCode::Char(c) if app.search_active => {
+                    app.search_query.push(c);
+                    app.selected_index = 0;
+                }
+                KeyCode::Backspace if app.search_active => {
+                    app.search_query.pop();
+                    app.selected_index = 0;
+                }
+                KeyCode::Char(c)
+                    if !app.search_active
+                        && !app.dialog_open()
+                        && c.is_ascii_digit()
+                        && c != '0' =>
+                {
+                    if let Some(digit) = c.to_digit(10) {
+                        app.set_tab_index((digit - 1) as usize);
+                    }
+                }
+                KeyCode::Char('q') => app.quit(),
+                KeyCode::Tab if !app.dialog_open() => app.next_tab(),
+                KeyCode::Down | KeyCode::Char('j') => {
+                    if app.show_confirmation {
+                        app.delete_scope = app.delete_scope.next(&app.available_scopes);
+                    } else if app.show_relink {
+                        app.relink_target = app.relink_target.toggle();
+                    } else if app.show_warnings {
+                        app.select_next_warning();
+                    } else if let Some(view) = &mut app.changes {
+                        view.scroll += 1;
+                    } else {
+                        app.select_next();
+                    }
+                }
+                KeyCode::Up | KeyCode::Char('k') => {
+                    if app.show_confirmation {
+                        app.delete_scope = app.delete_scope.prev(&app.available_scopes);
+                    } else if app.show_relink {
+                        app.relink_target = app.relink_target.toggle();
+                    } else if app.show_warnings {
+                        app.select_prev_warning();
+                    } else if let Some(view) = &mut app.changes {
+                        view.scroll = view.scroll.saturating_sub(1);
+                    } else {
+                        app.select_prev();
+                    }
+                }
+                KeyCode::Char('i') if !app.show_confirmation => {
+                    app.toggle_details();
+                }
+                KeyCode::Char('i') => {
+                    app.include_incomplete = !app.include_incomplete;
+                }
+                KeyCode::Char('w') if !app.dialog_open() => {
+                    app.toggle_warnings();
+                }
+                KeyCode::Char('t') | KeyCode::Char('d') if !app.dialog_open() => {
+                    app.request_delete();
+                }
+                KeyCode::Char('l') if !app.dialog_open() && !app.is_scanning() => {
+                    app.request_relink();
+                }
+                KeyCode::Char('c') if !app.dialog_open() => {
+                    app.toggle_changes();
+                }
+                KeyCode::Left if app.changes.is_some() => app.shift_changes_base(true),
+                KeyCode::Right if app.changes.is_some() => app.shift_changes_base(false),
+                KeyCode::Left if !app.dialog_open() => app.collapse(),
+                KeyCode::Right if !app.dialog_open() => app.expand(),
+                KeyCode::Char(' ') if !app.dialog_open() => {
+                    app.toggle_expanded();
+                }
+                KeyCode::Char('g') if !app.dialog_open() => {
+                    app.jump_to_related();
+                }
+                KeyCode::Char('f') if !app.dialog_open() && !app.search_active => {
+                    app.filter = app.filter.next();
+                }
+                KeyCode::Char('K') if !app.dialog_open() && !app.search_active => {
+                    app.kind_filter = app.kind_filter.next();
+                }
+                KeyCode::Char('s') if !app.dialog_open() && !app.search_active => {
+                    app.sort_by = app.sort_by.next();
+                }
+                KeyCode::Char('S') if !app.dialog_open() && !app.search_active => {
+                    app.sort_order = app.sort_order.toggle();
+                }
+                KeyCode::Char('/') if !app.dialog_open() => {
+                    app.search_active = true;
+                }
+                KeyCode::Char('r') if !app.dialog_open() && !app.search_active => {
+                    // Torrents finish between scans; refresh them for the new nodes
+                    if let Ok(torrents) = qbit.get_torrents().await {
+                        app.torrents = torrents;
                     }
+                    start_scan(source.as_ref(), &mut app);
+                    last_scan = Instant::now();
+                }
+                KeyCode::Enter if app.show_confirmation => {
+                    app.confirm_delete();
                 }
+                KeyCode::Enter if app.show_relink => {
+                    app.confirm_relink();
+                }
+                _ => {}
             }
         }
 
@@ -323,6 +498,40 @@ async fn main() -> Result<()> {
             }
         }
 
+        if let Some(w) = &watcher {
+            while let Ok(event) = w.events.try_recv() {
+                match event {
+                    WatchEvent::Changes(changes) => app.apply_changes(changes),
+                    WatchEvent::RescanNeeded => rescan_due = true,
+                    WatchEvent::Unavailable(reason) => {
+                        app.watch = WatchStatus::Polling {
+                            every: poll_every,
+                            reason,
+                        };
+                        rescan_due = true;
+                    }
+                }
+            }
+            if matches!(app.watch, WatchStatus::Polling { .. }) {
+                watcher = None;
+            }
+        }
+        if matches!(app.watch, WatchStatus::Polling { .. })
+            && !poll_every.is_zero()
+            && last_scan.elapsed() >= poll_every
+        {
+            rescan_due = true;
+        }
+        // Never swap the nodes under an open confirmation
+        if rescan_due && !app.is_scanning() && !app.dialog_open() {
+            if let Ok(torrents) = qbit.get_torrents().await {
+                app.torrents = torrents;
+            }
+            start_scan(source.as_ref(), &mut app);
+            last_scan = Instant::now();
+            rescan_due = false;
+        }
+
         app.tick();
     }
 
@@ -1,5 +1,6 @@
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TorrentInfo {
@@ -23,6 +24,9 @@ pub trait QbitClient: Send + Sync {
     #[allow(dead_code)]
     async fn get_torrent_files(&self, hash: &str) -> anyhow::Result<Vec<TorrentFile>>;
     async fn delete_torrent(&self, hash: &str, delete_files: bool) -> anyhow::Result<()>;
+    /// Folder unfinished torrents are kept in, if the client moves them on
+    /// completion.
+    async fn get_incomplete_dir(&self) -> anyhow::Result<Option<PathBuf>>;
 }
 
 pub struct MockQbitClient;
@@ -71,6 +75,10 @@ impl QbitClient for MockQbitClient {
     async fn delete_torrent(&self, _hash: &str, _delete_files: bool) -> anyhow::Result<()> {
         Ok(())
     }
+
+    async fn get_incomplete_dir(&self) -> anyhow::Result<Option<PathBuf>> {
+        Ok(None)
+    }
 }
 
 pub struct RealQbitClient {
@@ -124,4 +132,23 @@ impl QbitClient for RealQbitClient {
             .await?;
         Ok(())
     }
+
+    async fn get_incomplete_dir(&self) -> anyhow::Result<Option<PathBuf>> {
+        let url = format!("{}/api/v2/app/preferences", self.base_url);
+        let prefs: Preferences = self.client.get(&url).send().await?.json().await?;
+        Ok(prefs
+            .temp_path_enabled
+            .then_some(prefs.temp_path)
+            .filter(|path| !path.is_empty())
+            .map(PathBuf::from))
+    }
+}
+
+/// The part of qBittorrent's preferences ratatidy reads.
+#[derive(Debug, Deserialize)]
+struct Preferences {
+    #[serde(default)]
+    temp_path_enabled: bool,
+    #[serde(default)]
+    temp_path: String,
 }
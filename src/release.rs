@@ -0,0 +1,421 @@
+use std::ffi::OsStr;
+use std::path::Path;
+
+pub const VIDEO_EXTENSIONS: &[&str] = &[
+    "mkv", "mp4", "avi", "mov", "m4v", "wmv", "webm", "ts", "m2ts", "mpg", "mpeg",
+];
+
+/// Other extensions stripped before parsing, so a subtitle or a partial
+/// download parses like its video.
+const OTHER_EXTENSIONS: &[&str] = &[
+    "srt", "sub", "idx", "ass", "ssa", "nfo", "txt", "jpg", "png", "!qb", "part",
+];
+
+/// Tags that say nothing we keep but still end the title, e.g.
+/// `Movie.EXTENDED.1080p`.
+const OTHER_TAGS: &[&str] = &[
+    "repack",
+    "proper",
+    "extended",
+    "remastered",
+    "unrated",
+    "uncut",
+    "complete",
+    "internal",
+    "limited",
+    "multi",
+    "imax",
+    "hdr",
+    "hdr10",
+    "dv",
+    "10bit",
+    "aac",
+    "ac3",
+    "dts",
+    "ddp",
+    "atmos",
+];
+
+/// What a torrent, folder or file name says about its release, e.g.
+/// `The.Bear.S01E02.1080p.WEB-DL.x264-GRP`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Release {
+    pub title: String,
+    pub year: Option<u16>,
+    pub season: Option<u32>,
+    pub episode: Option<u32>,
+    pub resolution: Option<&'static str>,
+    pub source: Option<&'static str>,
+    pub codec: Option<&'static str>,
+    pub group: Option<String>,
+}
+
+impl Release {
+    pub fn is_episodic(&self) -> bool {
+        self.season.is_some() || self.episode.is_some()
+    }
+
+    /// Whether a year, episode or quality was recognised after the title.
+    pub fn is_tagged(&self) -> bool {
+        self.year.is_some()
+            || self.is_episodic()
+            || self.resolution.is_some()
+            || self.source.is_some()
+            || self.codec.is_some()
+    }
+
+    /// `S01E02`, `S01` or `E02`.
+    pub fn episode_label(&self) -> Option<String> {
+        match (self.season, self.episode) {
+            (Some(s), Some(e)) => Some(format!("S{:02}E{:02}", s, e)),
+            (Some(s), None) => Some(format!("S{:02}", s)),
+            (None, Some(e)) => Some(format!("E{:02}", e)),
+            (None, None) => None,
+        }
+    }
+
+    /// Vertical resolution, for sorting by quality. 0 when unknown.
+    pub fn resolution_rank(&self) -> u32 {
+        self.resolution
+            .and_then(|r| r.trim_end_matches(['p', 'i']).parse().ok())
+            .unwrap_or(0)
+    }
+
+    /// Source, codec and release group, e.g. `WEB-DL · H.264 · GRP`.
+    pub fn tags(&self) -> Vec<&str> {
+        [self.source, self.codec, self.group.as_deref()]
+            .into_iter()
+            .flatten()
+            .collect()
+    }
+
+    /// Whether any parsed field contains `query`, which must be lowercase.
+    pub fn matches(&self, query: &str) -> bool {
+        let year = self.year.map(|y| y.to_string());
+        [
+            Some(self.title.as_str()),
+            year.as_deref(),
+            self.episode_label().as_deref(),
+            self.resolution,
+            self.source,
+            self.codec,
+            self.group.as_deref(),
+        ]
+        .into_iter()
+        .flatten()
+        .any(|field| field.to_lowercase().contains(query))
+    }
+
+    /// Takes the year and quality from `other` where this release has none,
+    /// e.g. from the video file inside a plainly named folder.
+    pub fn fill_missing(&mut self, other: &Release) {
+        self.year = self.year.or(other.year);
+        self.resolution = self.resolution.or(other.resolution);
+        self.source = self.source.or(other.source);
+        self.codec = self.codec.or(other.codec);
+        if self.group.is_none() {
+            self.group = other.group.clone();
+        }
+    }
+}
+
+pub fn is_video(path: &Path) -> bool {
+    path.extension()
+        .and_then(OsStr::to_str)
+        .is_some_and(|ext| VIDEO_EXTENSIONS.iter().any(|v| ext.eq_ignore_ascii_case(v)))
+}
+
+fn strip_extensions(mut name: &str) -> &str {
+    // At most a partial suffix on top of the real extension
+    for _ in 0..2 {
+        match name.rsplit_once('.') {
+            Some((stem, ext))
+                if VIDEO_EXTENSIONS
+                    .iter()
+                    .chain(OTHER_EXTENSIONS)
+                    .any(|known| ext.eq_ignore_ascii_case(known)) =>
+            {
+                name = stem
+            }
+            _ => break,
+        }
+    }
+    name
+}
+
+/// Words of a name. Dashes stay inside words, so `Spider-Man` and `WEB-DL`
+/// are kept whole.
+fn tokens(name: &str) -> Vec<&str> {
+    name.split(['.', '_', ' ', '(', ')', '[', ']', '{', '}', ','])
+        .filter(|t| t.chars().any(char::is_alphanumeric))
+        .collect()
+}
+
+fn number<T: std::str::FromStr>(s: &str, max_len: usize) -> Option<T> {
+    if s.is_empty() || s.len() > max_len || !s.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    s.parse().ok()
+}
+
+fn year(token: &str) -> Option<u16> {
+    number(token, 4).filter(|y| (1900..2100).contains(y))
+}
+
+/// `S01`, `S01E02` or `S01E02E03`, also with a range like `S01-S03`.
+fn season_episode(token: &str) -> Option<(u32, Option<u32>)> {
+    let first = token.split('-').next()?;
+    let rest = first.strip_prefix(['s', 'S'])?;
+    let mut parts = rest.split(['e', 'E']);
+    let season = number(parts.next()?, 2)?;
+    let mut episode = None;
+    for part in parts {
+        let ep: u32 = number(part, 3)?;
+        episode.get_or_insert(ep);
+    }
+    Some((season, episode))
+}
+
+fn resolution(token: &str) -> Option<&'static str> {
+    Some(match token.to_lowercase().as_str() {
+        "480p" => "480p",
+        "576p" => "576p",
+        "720p" => "720p",
+        "1080p" => "1080p",
+        "1080i" => "1080i",
+        "2160p" | "4k" | "uhd" => "2160p",
+        "4320p" | "8k" => "4320p",
+        _ => return None,
+    })
+}
+
+fn source(token: &str) -> Option<&'static str> {
+    Some(match token.to_lowercase().as_str() {
+        "bluray" | "blu-ray" | "bdrip" | "brrip" => "BluRay",
+        "remux" | "bdremux" => "Remux",
+        "web-dl" | "webdl" => "WEB-DL",
+        "webrip" | "web-rip" => "WEBRip",
+        "web" => "WEB",
+        "hdtv" => "HDTV",
+        "dvdrip" => "DVDRip",
+        "dvd" | "dvd5" | "dvd9" => "DVD",
+        "hdrip" => "HDRip",
+        _ => return None,
+    })
+}
+
+/// `Season 01`, `Season.1` or `Season01`, with the number of tokens used.
+fn season_word(lower: &str, next: Option<&str>) -> Option<(u32, usize)> {
+    match lower.strip_prefix("season")? {
+        "" => Some((number(next?, 3)?, 2)),
+        n => Some((number(n, 3)?, 1)),
+    }
+}
+
+/// Codecs, including `H.264` split in two by the dot.
+fn codec(token: &str, next: Option<&str>) -> Option<(&'static str, usize)> {
+    let lower = token.to_lowercase();
+    let codec = match lower.as_str() {
+        "x264" | "h264" | "avc" => "H.264",
+        "x265" | "h265" | "hevc" => "H.265",
+        "av1" => "AV1",
+        "xvid" => "XviD",
+        "h" => {
+            return match next {
+                Some("264") => Some(("H.264", 2)),
+                Some("265") => Some(("H.265", 2)),
+                _ => None,
+            };
+        }
+        _ => return None,
+    };
+    Some((codec, 1))
+}
+
+/// Parses a release name. The title is everything before the first tag;
+/// a name without tags is all title.
+pub fn parse(name: &str) -> Release {
+    let name = strip_extensions(name.trim());
+
+    // `[Group] Title ...`
+    let (group, name) = match name.strip_prefix('[').and_then(|n| n.split_once(']')) {
+        Some((group, rest)) => (Some(group.trim().to_string()), rest),
+        None => (None, name),
+    };
+
+    // `... x264-GRP`, only trusted once the tags have started, so titles
+    // like `Spider-Man` keep their dash, and unless the dash belongs to a
+    // tag like `WEB-DL`
+    let last_is_tag = tokens(name)
+        .last()
+        .is_some_and(|last| parse_tokens(&[last]).1);
+    if let Some((body, suffix)) = name.rsplit_once('-').filter(|_| !last_is_tag) {
+        let suffix = suffix.split('[').next().unwrap_or_default().trim();
+        let (mut release, tagged) = parse_tokens(&tokens(body));
+        if tagged && !suffix.is_empty() && tokens(suffix).len() == 1 {
+            release.group = group.or(Some(suffix.to_string()));
+            return release;
+        }
+    }
+
+    if let Some((title, episode, tags)) = absolute_episode(name) {
+        let mut release = parse_tokens(&tokens(title)).0;
+        release.episode.get_or_insert(episode);
+        release.fill_missing(&parse_tokens(&tags).0);
+        release.group = group;
+        return release;
+    }
+
+    let mut release = parse_tokens(&tokens(name)).0;
+    release.group = group;
+    release
+}
+
+/// `Title - 05 (1080p)`: an absolute episode number right after a spaced
+/// dash, as anime releases number them. Returns the title part, the episode
+/// and the tokens after it.
+fn absolute_episode(name: &str) -> Option<(&str, u32, Vec<&str>)> {
+    let (title, rest) = name.split_once(" - ")?;
+    let rest = tokens(rest);
+    let (first, tags) = rest.split_first()?;
+    let episode = number(first, 4).filter(|_| year(first).is_none())?;
+    Some((title, episode, tags.to_vec()))
+}
+
+/// Returns the release and whether any tag was found.
+fn parse_tokens(tokens: &[&str]) -> (Release, bool) {
+    let mut release = Release::default();
+    let mut title_end = None;
+    let mut i = 0;
+    while i < tokens.len() {
+        let token = tokens[i];
+        let lower = token.to_lowercase();
+        let next = tokens.get(i + 1).copied();
+        let mut consumed = 1;
+        let tag = if let Some((season, episode)) = season_episode(token) {
+            release.season.get_or_insert(season);
+            if release.episode.is_none() {
+                release.episode = episode;
+            }
+            true
+        } else if let Some((season, used)) = season_word(&lower, next).filter(|&(_, used)| {
+            // `Open Season 2 (2008)` is a title: mid-name, only a `Season N`
+            // without a year after it counts
+            i == 0 || !tokens[i + used..].iter().any(|t| year(t).is_some())
+        }) {
+            release.season.get_or_insert(season);
+            consumed = used;
+            true
+        } else if let Some(resolution) = resolution(token) {
+            release.resolution.get_or_insert(resolution);
+            true
+        } else if let Some(source) = source(token) {
+            release.source.get_or_insert(source);
+            true
+        } else if let Some((codec, used)) = codec(token, next) {
+            release.codec.get_or_insert(codec);
+            consumed = used;
+            true
+        } else if let Some(y) = year(token).filter(|_| i > 0 && title_end.is_none()) {
+            // `Blade.Runner.2049.2017`: only the last of two years is the
+            // release year
+            if next.and_then(year).is_some() {
+                false
+            } else {
+                release.year = Some(y);
+                true
+            }
+        } else {
+            OTHER_TAGS.contains(&lower.as_str())
+        };
+        if tag && title_end.is_none() {
+            title_end = Some(i);
+        }
+        i += consumed;
+    }
+    let found = title_end.is_some();
+    release.title = tokens[..title_end.unwrap_or(tokens.len())].join(" ");
+    (release, found)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Name, title, year, season, episode.
+    type Case = (
+        &'static str,
+        &'static str,
+        Option<u16>,
+        Option<u32>,
+        Option<u32>,
+    );
+
+    #[test]
+    fn parses_release_names() {
+        let cases: &[Case] = &[
+            (
+                "The.Bear.S01E02.1080p.WEB-DL.x264-GRP",
+                "The Bear",
+                None,
+                Some(1),
+                Some(2),
+            ),
+            (
+                "Open Season 2 (2008)",
+                "Open Season 2",
+                Some(2008),
+                None,
+                None,
+            ),
+            ("Show Name Season 2 1080p", "Show Name", None, Some(2), None),
+            ("Season 01", "", None, Some(1), None),
+            (
+                "[SubsPlease] Frieren - 05 (1080p)",
+                "Frieren",
+                None,
+                None,
+                Some(5),
+            ),
+            (
+                "Blade.Runner.2049.2017",
+                "Blade Runner 2049",
+                Some(2017),
+                None,
+                None,
+            ),
+            ("Spider-Man (2002)", "Spider-Man", Some(2002), None, None),
+            ("9-1-1.S01E01", "9-1-1", None, Some(1), Some(1)),
+            ("Inception (2010)", "Inception", Some(2010), None, None),
+        ];
+        for &(name, title, year, season, episode) in cases {
+            let release = parse(name);
+            assert_eq!(
+                (
+                    release.title.as_str(),
+                    release.year,
+                    release.season,
+                    release.episode
+                ),
+                (title, year, season, episode),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn parses_tags_and_group() {
+        let release = parse("Heat.1995.2160p.UHD.BluRay.H.264-GRP.mkv");
+        assert_eq!(release.title, "Heat");
+        assert_eq!(release.year, Some(1995));
+        assert_eq!(release.resolution, Some("2160p"));
+        assert_eq!(release.source, Some("BluRay"));
+        assert_eq!(release.codec, Some("H.264"));
+        assert_eq!(release.group.as_deref(), Some("GRP"));
+
+        let release = parse("[SubsPlease] Frieren - 05 (1080p)");
+        assert_eq!(release.resolution, Some("1080p"));
+        assert_eq!(release.group.as_deref(), Some("SubsPlease"));
+    }
+}
@@ -0,0 +1,72 @@
+use crate::duplicates::read_up_to;
+use crate::scanner::Scanner;
+use anyhow::{Context, Result, bail};
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+
+/// Replaces `replace` with a hardlink to `keep` and returns the new link
+/// count of `keep`.
+///
+/// Both files must live on the same device and have identical content, which
+/// is checked byte for byte right before the swap. The link is created under
+/// a temporary name next to `replace` and renamed over it, so `replace` is
+/// never missing. A hardlink shares one set of inode metadata, so `keep`'s
+/// owner, permissions and timestamps are left untouched and `replace` takes
+/// them over.
+pub fn relink(keep: &Path, replace: &Path) -> Result<u32> {
+    let keep_md = fs::symlink_metadata(keep).with_context(|| keep.display().to_string())?;
+    let replace_md =
+        fs::symlink_metadata(replace).with_context(|| replace.display().to_string())?;
+    if !keep_md.is_file() || !replace_md.is_file() {
+        bail!("only regular files can be relinked");
+    }
+
+    let (keep_key, _) = Scanner::get_file_info(keep, &keep_md)?;
+    let (replace_key, _) = Scanner::get_file_info(replace, &replace_md)?;
+    if keep_key == replace_key {
+        bail!("already hardlinked");
+    }
+    if keep_key.dev != replace_key.dev {
+        bail!("files are on different filesystems");
+    }
+    if keep_md.len() != replace_md.len() || !same_content(keep, replace)? {
+        bail!("content differs from {}", keep.display());
+    }
+
+    let staging = staging_path(replace)?;
+    let _ = fs::remove_file(&staging);
+    fs::hard_link(keep, &staging).context("failed to create hardlink")?;
+    if let Err(e) = fs::rename(&staging, replace) {
+        let _ = fs::remove_file(&staging);
+        return Err(e).context("failed to replace file with hardlink");
+    }
+
+    let md = fs::symlink_metadata(keep)?;
+    Ok(Scanner::get_file_info(keep, &md)?.1)
+}
+
+fn staging_path(path: &Path) -> Result<PathBuf> {
+    let name = path
+        .file_name()
+        .with_context(|| format!("{} has no file name", path.display()))?;
+    let mut staging = std::ffi::OsString::from(".");
+    staging.push(name);
+    staging.push(".ratatidy-relink");
+    Ok(path.with_file_name(staging))
+}
+
+fn same_content(a: &Path, b: &Path) -> Result<bool> {
+    let mut a = File::open(a)?;
+    let mut b = File::open(b)?;
+    let mut buf_a = vec![0; 1024 * 1024];
+    let mut buf_b = vec![0; 1024 * 1024];
+    loop {
+        let n = read_up_to(&mut a, &mut buf_a)?;
+        if read_up_to(&mut b, &mut buf_b)? != n || buf_a[..n] != buf_b[..n] {
+            return Ok(false);
+        }
+        if n == 0 {
+            return Ok(true);
+        }
+    }
+}
@@ -1,21 +1,30 @@
 use anyhow::Result;
-use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::path::{Path, PathBuf};
-use std::time::SystemTime;
+use std::time::{Duration, Instant, SystemTime};
 
+#[cfg(windows)]
+use std::os::windows::fs::OpenOptionsExt;
 #[cfg(windows)]
 use std::os::windows::io::AsRawHandle;
 #[cfg(windows)]
 use windows_sys::Win32::Storage::FileSystem::{
-    BY_HANDLE_FILE_INFORMATION, GetFileInformationByHandle,
+    BY_HANDLE_FILE_INFORMATION, FILE_FLAG_BACKUP_SEMANTICS, GetFileInformationByHandle,
 };
 
 #[cfg(unix)]
 use std::os::unix::fs::MetadataExt;
-use std::sync::Arc;
-use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
 
+use crate::cache::{CacheEntry, ChangeStamp, DirRecord, ScanCache, change_stamp};
+use crate::config::SymlinkPolicy;
+use crate::duplicates::{FileHashes, find_duplicates};
+use crate::filters::{FilterRules, IGNORE_FILE, IgnoreStack, ScanFilter, is_partial};
+use crate::snapshot::{self, Snapshot};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -26,8 +35,9 @@ pub struct FileKey {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ScanEvent {
-    FileScanned,
-    Finished(Vec<FileNode>),
+    Progress(ScanProgress),
+    Finished(Vec<FileNode>, ScanReport),
+    Cancelled,
     Error(String),
 }
 
@@ -42,175 +52,614 @@ pub struct FileNode {
     pub torrent_hash: Option<String>,
     pub is_seeding: bool,
     pub modified: Option<SystemTime>,
+    #[serde(default)]
+    pub kind: NodeKind,
+    /// Files on the other side (downloads vs media) with identical content
+    /// but a different inode: copies that could be hardlinks.
+    #[serde(default)]
+    pub duplicates: Vec<FileKey>,
+    /// Still being downloaded: a partial-file suffix, an unfinished torrent
+    /// or a path in the client's incomplete folder. Left out of orphan
+    /// filters and deletes unless asked for.
+    #[serde(default)]
+    pub incomplete: bool,
+    /// Followed symlinks that resolve to this file. Kept apart from `paths`,
+    /// which are its hardlinks, so they don't count against `nlink`.
+    #[serde(default)]
+    pub symlinks: Vec<PathBuf>,
 }
 
-#[derive(Serialize, Deserialize, Default)]
-struct ScanCache {
-    entries: HashMap<PathBuf, CacheEntry>,
+impl FileNode {
+    /// Every path the file was seen at: its hardlinks, then followed
+    /// symlinks.
+    pub fn all_paths(&self) -> impl Iterator<Item = &PathBuf> {
+        self.paths.iter().chain(&self.symlinks)
+    }
+
+    /// Paths that decide which roots list the file: its hardlinks, or its
+    /// symlinks when it was only reached through links. A download that a
+    /// media symlink points at stays a download orphan, as deleting it
+    /// frees the data and breaks the link.
+    pub fn located_paths(&self) -> &[PathBuf] {
+        if self.paths.is_empty() {
+            &self.symlinks
+        } else {
+            &self.paths
+        }
+    }
+
+    /// Hardlinks to this file that the scan did not see: links outside the
+    /// roots, or inside them but filtered out. Deleting every visible path
+    /// leaves the data on disk while any of these remain.
+    pub fn external_links(&self) -> u32 {
+        match self.kind {
+            NodeKind::File => self.nlink.saturating_sub(self.paths.len() as u32),
+            NodeKind::Symlink { .. } => 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum NodeKind {
+    #[default]
+    File,
+    /// A symlink kept as its own node under [`SymlinkPolicy::Report`].
+    Symlink { target: PathBuf, broken: bool },
+}
+
+/// Snapshot of a running scan. Sent at most every [`PROGRESS_INTERVAL`] so
+/// large trees don't flood the channel.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ScanProgress {
+    /// Root the most recently merged directory belongs to.
+    pub root: PathBuf,
+    pub current_dir: PathBuf,
+    pub files: usize,
+    pub bytes: u64,
+    /// File count of the previous scan, if there was one.
+    pub estimated_files: Option<usize>,
+    pub phase: ScanPhase,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ScanPhase {
+    #[default]
+    Walking,
+    /// Hashing same-sized download and media files to find copies.
+    Hashing { done: usize, total: usize },
+}
+
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Shared flag that stops a running scan. Walkers check it before each
+/// directory, so cancelling takes effect within one `read_dir`.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// How a finished scan used the cache.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ScanReport {
+    /// Entries replayed from unchanged directories without touching the file.
+    pub reused: usize,
+    /// Entries whose cached link count was confirmed by a fresh stat.
+    pub revalidated: usize,
+    /// Entries that were stale and had to be read again.
+    pub refreshed: usize,
+    /// Paths that could not be read and were skipped.
+    pub issues: Vec<ScanIssue>,
+    /// Device layout of every scan root, download root first.
+    #[serde(default)]
+    pub roots: Vec<RootInfo>,
+}
+
+/// Which filesystem a scan root lives on. Hardlinks cannot cross devices, so
+/// a media root on a different device than the downloads can only hold
+/// copies, and so can any filesystem mounted inside it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RootInfo {
+    pub path: PathBuf,
+    pub is_download: bool,
+    /// Device of the root itself, `None` where the platform doesn't expose it.
+    pub dev: Option<u64>,
+    /// Directories below the root where another filesystem is mounted.
+    pub mounts: Vec<MountPoint>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MountPoint {
+    pub path: PathBuf,
+    pub dev: u64,
+}
+
+/// A path skipped during a scan, e.g. an unreadable directory or a file
+/// deleted while the scan was running.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScanIssue {
+    pub path: PathBuf,
+    pub reason: String,
 }
 
-#[derive(Serialize, Deserialize, Clone)]
-struct CacheEntry {
+impl ScanIssue {
+    fn new(path: &Path, error: impl std::fmt::Display) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            reason: error.to_string(),
+        }
+    }
+}
+
+pub struct Scanner {
+    pub download_dirs: Vec<PathBuf>,
+    pub media_dirs: Vec<PathBuf>,
+    pub threads: usize,
+    pub filters: FilterRules,
+    pub symlinks: SymlinkPolicy,
+    pub detect_duplicates: bool,
+    pub one_file_system: bool,
+    /// Number of snapshots to keep; 0 turns snapshots off.
+    pub snapshots: usize,
+}
+
+/// Files found directly inside one directory, sent from a walker thread to
+/// the collector that owns the node map.
+struct DirScan {
+    root: usize,
+    dir: PathBuf,
+    files: Vec<ScannedFile>,
+    issues: Vec<ScanIssue>,
+    listing: Option<DirRecord>,
+    /// Device mounted at this directory, if it differs from the parent's.
+    mounted: Option<u64>,
+}
+
+impl DirScan {
+    fn failed(root: usize, dir: PathBuf, error: impl std::fmt::Display) -> Self {
+        Self {
+            issues: vec![ScanIssue::new(&dir, error)],
+            ..Self::empty(root, dir)
+        }
+    }
+
+    fn empty(root: usize, dir: PathBuf) -> Self {
+        Self {
+            root,
+            dir,
+            files: Vec::new(),
+            issues: Vec::new(),
+            listing: None,
+            mounted: None,
+        }
+    }
+}
+
+/// Read-only state shared by every walker task of one scan.
+struct WalkContext<'s> {
+    /// Scan roots and whether each one is a download root.
+    roots: &'s [(PathBuf, bool)],
+    /// Compiled filters, one per root.
+    filters: &'s [ScanFilter],
+    cache: &'s ScanCache,
+    cancel: &'s CancelToken,
+    symlinks: SymlinkPolicy,
+    /// Don't descend into directories on another device, like `find -xdev`.
+    one_file_system: bool,
+}
+
+struct ScannedFile {
+    path: PathBuf,
     key: FileKey,
     nlink: u32,
     size: u64,
     modified: Option<SystemTime>,
+    changed: Option<ChangeStamp>,
+    kind: NodeKind,
+    origin: CacheUse,
+    /// Reached through a followed symlink, so `path` is the link's.
+    followed: bool,
 }
 
-impl ScanCache {
-    fn load() -> Self {
-        if let Some(proj_dirs) = directories::ProjectDirs::from("com", "ratatidy", "ratatidy") {
-            let cache_path = proj_dirs.cache_dir().join("scan_cache.json");
-            if cache_path.exists() {
-                if let Ok(content) = fs::read_to_string(cache_path) {
-                    return serde_json::from_str(&content).unwrap_or_default();
-                }
-            }
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CacheUse {
+    /// From a replayed listing, `lstat`-ed and matching the cached entry.
+    Reused,
+    /// Stat-ed and matched the cached entry.
+    Revalidated,
+    /// Stat-ed and the cached entry was stale.
+    Refreshed,
+    /// Stat-ed with no cached entry.
+    Missed,
+    /// Never cached, e.g. a reported symlink.
+    Uncached,
+}
+
+/// Merges per-directory results from the walker threads. Owned by the scan
+/// thread, so the walkers only ever read the cache.
+struct Collector {
+    nodes: HashMap<FileKey, FileNode>,
+    progress: ScanProgress,
+    last_progress: Instant,
+    /// New or refreshed cache entries and listings, with their root.
+    fresh: Vec<(usize, PathBuf, CacheEntry)>,
+    listings: Vec<(usize, PathBuf, DirRecord)>,
+    /// Every directory merged so far, to prune the cache of ones that are gone.
+    visited: Vec<PathBuf>,
+    /// Files found below each root.
+    root_files: Vec<usize>,
+    /// Link count and stamp of every node stat-ed during this scan.
+    stamps: HashMap<FileKey, (u32, Option<ChangeStamp>)>,
+    report: ScanReport,
+}
+
+impl Collector {
+    fn new(estimated_files: Option<usize>, roots: Vec<RootInfo>) -> Self {
+        Self {
+            nodes: HashMap::new(),
+            progress: ScanProgress {
+                estimated_files,
+                ..Default::default()
+            },
+            last_progress: Instant::now(),
+            fresh: Vec::new(),
+            listings: Vec::new(),
+            visited: Vec::new(),
+            root_files: vec![0; roots.len()],
+            stamps: HashMap::new(),
+            report: ScanReport {
+                roots,
+                ..Default::default()
+            },
+        }
+    }
+
+    fn add_dir(&mut self, dir: DirScan, roots: &[(PathBuf, bool)], sender: &Sender<ScanEvent>) {
+        let (root, is_download) = &roots[dir.root];
+        if let Some(record) = dir.listing {
+            self.listings.push((dir.root, dir.dir.clone(), record));
+        }
+        self.visited.push(dir.dir.clone());
+        self.report.issues.extend(dir.issues);
+        if let Some(dev) = dir.mounted {
+            self.report.roots[dir.root].mounts.push(MountPoint {
+                path: dir.dir.clone(),
+                dev,
+            });
+        }
+        for file in dir.files {
+            self.progress.files += 1;
+            self.root_files[dir.root] += 1;
+            self.progress.bytes += file.size;
+            self.add_file(dir.root, file, *is_download);
+        }
+
+        if self.last_progress.elapsed() >= PROGRESS_INTERVAL {
+            self.progress.root = root.clone();
+            self.progress.current_dir = dir.dir;
+            self.last_progress = Instant::now();
+            let _ = sender.send(ScanEvent::Progress(self.progress.clone()));
         }
-        Self::default()
     }
 
-    fn save(&self) -> Result<()> {
-        if let Some(proj_dirs) = directories::ProjectDirs::from("com", "ratatidy", "ratatidy") {
-            let cache_dir = proj_dirs.cache_dir();
-            fs::create_dir_all(cache_dir)?;
-            let cache_path = cache_dir.join("scan_cache.json");
-            let content = serde_json::to_string(self)?;
-            fs::write(cache_path, content)?;
+    fn add_file(&mut self, root: usize, file: ScannedFile, is_download: bool) {
+        match file.origin {
+            CacheUse::Reused => self.report.reused += 1,
+            CacheUse::Revalidated => self.report.revalidated += 1,
+            CacheUse::Refreshed => self.report.refreshed += 1,
+            CacheUse::Missed | CacheUse::Uncached => {}
+        }
+        if matches!(file.origin, CacheUse::Refreshed | CacheUse::Missed) {
+            self.fresh.push((
+                root,
+                file.path.clone(),
+                CacheEntry {
+                    key: file.key,
+                    nlink: file.nlink,
+                    size: file.size,
+                    modified: file.modified,
+                    changed: file.changed,
+                    hashes: None,
+                },
+            ));
+        }
+
+        let node = self.nodes.entry(file.key).or_insert_with(|| FileNode {
+            key: file.key,
+            size: file.size,
+            nlink: file.nlink,
+            paths: Vec::new(),
+            has_downloads: false,
+            has_media: false,
+            torrent_hash: None,
+            is_seeding: false,
+            modified: file.modified,
+            kind: file.kind,
+            duplicates: Vec::new(),
+            incomplete: false,
+            symlinks: Vec::new(),
+        });
+
+        if let Entry::Vacant(stamp) = self.stamps.entry(file.key) {
+            stamp.insert((file.nlink, file.changed));
+            node.nlink = file.nlink;
+        }
+
+        node.incomplete |= is_partial(&file.path);
+        // Like `FileNode::located_paths`, links only decide where a file is
+        // listed until one of its hardlinks turns up
+        if file.followed {
+            node.symlinks.push(file.path);
+            if !node.paths.is_empty() {
+                return;
+            }
+        } else {
+            if node.paths.is_empty() {
+                node.has_downloads = false;
+                node.has_media = false;
+            }
+            node.paths.push(file.path);
+        }
+        if is_download {
+            node.has_downloads = true;
+        } else {
+            node.has_media = true;
         }
-        Ok(())
     }
-}
 
-pub struct Scanner {
-    pub download_dir: PathBuf,
-    pub media_dirs: Vec<PathBuf>,
+    /// Writes the scan back into the cache and returns the final nodes.
+    ///
+    /// Every node's fresh link count and stamp is copied to all of its cache
+    /// entries. Entries for files and directories this scan didn't see are
+    /// dropped.
+    fn finish(mut self, cache: &mut ScanCache) -> (Vec<FileNode>, ScanReport) {
+        for (root, path, entry) in self.fresh {
+            cache.roots[root].entries.insert(path, entry);
+            cache.roots[root].mark_dirty();
+        }
+        for (root, path, record) in self.listings {
+            cache.roots[root].dirs.insert(path, record);
+            cache.roots[root].mark_dirty();
+        }
+        for (root, count) in cache.roots.iter_mut().zip(self.root_files) {
+            if root.file_count != Some(count) {
+                root.file_count = Some(count);
+                root.mark_dirty();
+            }
+        }
+
+        for root in &mut self.report.roots {
+            root.mounts.sort_by(|a, b| a.path.cmp(&b.path));
+        }
+        let mut nodes: Vec<FileNode> = self.nodes.into_values().collect();
+        for node in &mut nodes {
+            node.paths.sort();
+            node.symlinks.sort();
+            if let Some(&(nlink, changed)) = self.stamps.get(&node.key) {
+                node.nlink = nlink;
+                for path in node.all_paths() {
+                    for root in &mut cache.roots {
+                        if let Some(entry) = root.entries.get_mut(path)
+                            && (entry.nlink != nlink || entry.changed != changed)
+                        {
+                            entry.nlink = nlink;
+                            entry.changed = changed;
+                            root.mark_dirty();
+                        }
+                    }
+                }
+            }
+        }
+
+        let visited: HashSet<&Path> = self.visited.iter().map(PathBuf::as_path).collect();
+        let seen: HashSet<&Path> = nodes
+            .iter()
+            .flat_map(|n| n.all_paths().map(PathBuf::as_path))
+            .collect();
+        for root in &mut cache.roots {
+            root.prune(&visited, &seen);
+        }
+        (nodes, self.report)
+    }
 }
 
 impl Scanner {
-    pub fn new(download_dir: PathBuf, media_dirs: Vec<PathBuf>) -> Self {
+    pub fn new(download_dirs: Vec<PathBuf>, media_dirs: Vec<PathBuf>) -> Self {
         Self {
-            download_dir,
+            download_dirs,
             media_dirs,
+            threads: 0,
+            filters: FilterRules::default(),
+            symlinks: SymlinkPolicy::default(),
+            detect_duplicates: false,
+            one_file_system: false,
+            snapshots: 0,
         }
     }
 
-    pub fn scan_async(&self, sender: std::sync::mpsc::Sender<ScanEvent>) {
-        let download_dir = self.download_dir.clone();
-        let media_dirs = self.media_dirs.clone();
-        let scanner_clone = Self::new(download_dir.clone(), media_dirs.clone());
+    /// Number of walker threads, 0 means one per CPU.
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
 
-        std::thread::spawn(move || {
-            let mut nodes: HashMap<FileKey, FileNode> = HashMap::new();
-            let cache = Arc::new(Mutex::new(ScanCache::load()));
+    pub fn with_filters(mut self, filters: FilterRules) -> Self {
+        self.filters = filters;
+        self
+    }
+
+    pub fn with_symlinks(mut self, symlinks: SymlinkPolicy) -> Self {
+        self.symlinks = symlinks;
+        self
+    }
+
+    /// Hash same-sized download and media files after the walk to find
+    /// copies that should have been hardlinks.
+    pub fn with_duplicate_detection(mut self, enabled: bool) -> Self {
+        self.detect_duplicates = enabled;
+        self
+    }
+
+    /// Skip directories where another filesystem is mounted. They are still
+    /// listed as mount points in the [`ScanReport`].
+    pub fn with_one_file_system(mut self, enabled: bool) -> Self {
+        self.one_file_system = enabled;
+        self
+    }
 
-            if let Err(e) =
-                scanner_clone.scan_async_dir(&download_dir, true, &mut nodes, &sender, &cache)
+    /// Save each finished scan as a snapshot, keeping the latest `keep`.
+    pub fn with_snapshots(mut self, keep: usize) -> Self {
+        self.snapshots = keep;
+        self
+    }
+
+    /// Starts a scan on a background thread. The returned token aborts it;
+    /// a cancelled scan sends [`ScanEvent::Cancelled`] and leaves the cache
+    /// untouched.
+    pub fn scan_async(&self, sender: Sender<ScanEvent>) -> CancelToken {
+        let cancel = CancelToken::default();
+        let token = cancel.clone();
+        let mut roots: Vec<(PathBuf, bool)> = self
+            .download_dirs
+            .iter()
+            .map(|d| (d.clone(), true))
+            .collect();
+        roots.extend(self.media_dirs.iter().map(|m| (m.clone(), false)));
+        let threads = self.threads;
+        let rules = self.filters.clone();
+        let symlinks = self.symlinks;
+        let detect_duplicates = self.detect_duplicates;
+        let one_file_system = self.one_file_system;
+        let snapshots = self.snapshots;
+
+        std::thread::spawn(move || {
+            let filters = match roots
+                .iter()
+                .map(|(root, _)| rules.compile(root))
+                .collect::<Result<Vec<_>>>()
             {
-                let _ = sender.send(ScanEvent::Error(e.to_string()));
-                return;
-            }
+                Ok(filters) => filters,
+                Err(e) => {
+                    let _ = sender.send(ScanEvent::Error(format!("Invalid filter pattern: {}", e)));
+                    return;
+                }
+            };
 
-            for media_dir in &media_dirs {
-                if let Err(e) =
-                    scanner_clone.scan_async_dir(media_dir, false, &mut nodes, &sender, &cache)
-                {
+            let pool = match rayon::ThreadPoolBuilder::new()
+                .num_threads(threads)
+                .thread_name(|i| format!("ratatidy-scan-{}", i))
+                .build()
+            {
+                Ok(pool) => pool,
+                Err(e) => {
                     let _ = sender.send(ScanEvent::Error(e.to_string()));
                     return;
                 }
-            }
+            };
+
+            let root_paths: Vec<PathBuf> = roots.iter().map(|(path, _)| path.clone()).collect();
+            let mut cache = ScanCache::load(&root_paths);
+            let root_info = roots
+                .iter()
+                .map(|(path, is_download)| RootInfo {
+                    path: path.clone(),
+                    is_download: *is_download,
+                    dev: fs::metadata(path)
+                        .ok()
+                        .and_then(|m| dir_key(path, &m))
+                        .map(|k| k.dev),
+                    mounts: Vec::new(),
+                })
+                .collect();
+            let mut collector = Collector::new(cache.file_count(), root_info);
+
+            let ctx = WalkContext {
+                roots: &roots,
+                filters: &filters,
+                cache: &cache,
+                cancel: &cancel,
+                symlinks,
+                one_file_system,
+            };
 
-            // Save cache
-            if let Ok(cache) = cache.lock() {
-                let _ = cache.save();
+            walk(&ctx, &pool, &mut collector, &sender);
+
+            if cancel.is_cancelled() {
+                let _ = sender.send(ScanEvent::Cancelled);
+                return;
             }
 
-            let _ = sender.send(ScanEvent::Finished(nodes.into_values().collect()));
-        });
-    }
+            let walked = collector.progress.clone();
+            let (mut nodes, mut report) = collector.finish(&mut cache);
 
-    fn scan_async_dir(
-        &self,
-        path: &Path,
-        is_download: bool,
-        nodes: &mut HashMap<FileKey, FileNode>,
-        sender: &std::sync::mpsc::Sender<ScanEvent>,
-        cache: &Arc<Mutex<ScanCache>>,
-    ) -> Result<()> {
-        if !path.exists() {
-            return Ok(());
-        }
-
-        for entry in fs::read_dir(path)? {
-            let entry = entry?;
-            let metadata = entry.metadata()?;
-            let path = entry.path();
-
-            if metadata.is_dir() {
-                self.scan_async_dir(&path, is_download, nodes, sender, cache)?;
-            } else if metadata.is_file() {
-                let mtime = metadata.created().ok().or_else(|| metadata.modified().ok());
-                let size = metadata.len();
-
-                let (key, nlink) = {
-                    let mut cache_lock = cache.lock().unwrap();
-                    if let Some(entry) = cache_lock.entries.get(&path) {
-                        if entry.size == size && entry.modified == mtime {
-                            (entry.key, entry.nlink)
-                        } else {
-                            let (key, nlink) = self.get_file_info(&path, &metadata)?;
-                            cache_lock.entries.insert(
-                                path.clone(),
-                                CacheEntry {
-                                    key,
-                                    nlink,
-                                    size,
-                                    modified: mtime,
-                                },
-                            );
-                            (key, nlink)
+            if detect_duplicates {
+                let cached: HashMap<FileKey, FileHashes> = nodes
+                    .iter()
+                    .filter_map(|n| Some((n.key, n.all_paths().find_map(|p| cache.hashes(p))?)))
+                    .collect();
+                let last_progress = Mutex::new(Instant::now());
+                let dupes = pool.install(|| {
+                    find_duplicates(&nodes, &cached, &cancel, |done, total| {
+                        // Called from every hashing thread; skip the update
+                        // while another thread holds the clock
+                        let due = last_progress.try_lock().is_ok_and(|mut last| {
+                            let due = last.elapsed() >= PROGRESS_INTERVAL;
+                            if due {
+                                *last = Instant::now();
+                            }
+                            due
+                        });
+                        if due || done == total {
+                            let _ = sender.send(ScanEvent::Progress(ScanProgress {
+                                phase: ScanPhase::Hashing { done, total },
+                                ..walked.clone()
+                            }));
                         }
-                    } else {
-                        let (key, nlink) = self.get_file_info(&path, &metadata)?;
-                        cache_lock.entries.insert(
-                            path.clone(),
-                            CacheEntry {
-                                key,
-                                nlink,
-                                size,
-                                modified: mtime,
-                            },
-                        );
-                        (key, nlink)
-                    }
-                };
-
-                let node = nodes.entry(key).or_insert_with(|| FileNode {
-                    key,
-                    size,
-                    nlink,
-                    paths: Vec::new(),
-                    has_downloads: false,
-                    has_media: false,
-                    torrent_hash: None,
-                    is_seeding: false,
-                    modified: mtime,
+                    })
                 });
+                for node in &nodes {
+                    if let Some(&hashes) = dupes.hashes.get(&node.key) {
+                        for path in node.all_paths() {
+                            cache.set_hashes(path, hashes);
+                        }
+                    }
+                }
+                let mut copies = dupes.copies;
+                for node in &mut nodes {
+                    node.duplicates = copies.remove(&node.key).unwrap_or_default();
+                }
+                report.issues.extend(dupes.issues);
+            }
 
-                node.paths.push(path);
-                if is_download {
-                    node.has_downloads = true;
-                } else {
-                    node.has_media = true;
+            if cancel.is_cancelled() {
+                let _ = sender.send(ScanEvent::Cancelled);
+                return;
+            }
+            let _ = cache.save();
+            if snapshots > 0 {
+                let snapshot = Snapshot::new(nodes.clone(), report.roots.clone());
+                if let Err(e) = snapshot::record(snapshot, snapshots) {
+                    let dir = snapshot::snapshot_dir().unwrap_or_default();
+                    report
+                        .issues
+                        .push(ScanIssue::new(&dir, format!("snapshot not saved: {:#}", e)));
                 }
-                let _ = sender.send(ScanEvent::FileScanned);
             }
-        }
-        Ok(())
+            let _ = sender.send(ScanEvent::Finished(nodes, report));
+        });
+
+        token
     }
 
     #[cfg(windows)]
-    fn get_file_info(&self, path: &Path, _metadata: &fs::Metadata) -> Result<(FileKey, u32)> {
+    pub fn get_file_info(path: &Path, _metadata: &fs::Metadata) -> Result<(FileKey, u32)> {
         let file = fs::File::open(path)?;
         let handle = file.as_raw_handle();
         let mut info: BY_HANDLE_FILE_INFORMATION = unsafe { std::mem::zeroed() };
@@ -228,7 +677,7 @@ impl Scanner {
     }
 
     #[cfg(unix)]
-    fn get_file_info(&self, _path: &Path, metadata: &fs::Metadata) -> Result<(FileKey, u32)> {
+    pub fn get_file_info(_path: &Path, metadata: &fs::Metadata) -> Result<(FileKey, u32)> {
         let key = FileKey {
             dev: metadata.dev(),
             inode: metadata.ino(),
@@ -236,3 +685,568 @@ impl Scanner {
         Ok((key, metadata.nlink() as u32))
     }
 }
+
+/// Walks every root on `pool`, merging each directory into `collector` as
+/// it arrives.
+fn walk(
+    ctx: &WalkContext,
+    pool: &rayon::ThreadPool,
+    collector: &mut Collector,
+    sender: &Sender<ScanEvent>,
+) {
+    let (result_tx, result_rx) = mpsc::channel::<DirScan>();
+    std::thread::scope(|s| {
+        s.spawn(move || {
+            pool.scope(|scope| {
+                for root in 0..ctx.roots.len() {
+                    let task = DirTask::root(ctx, root);
+                    let tx = result_tx.clone();
+                    scope.spawn(move |scope| walk_dir(scope, ctx, task, tx));
+                }
+            });
+            drop(result_tx);
+        });
+
+        for dir in result_rx {
+            collector.add_dir(dir, ctx.roots, sender);
+        }
+    });
+}
+
+/// Per-directory state handed down the walk.
+struct DirTask {
+    root: usize,
+    dir: PathBuf,
+    ignores: Arc<IgnoreStack>,
+    /// Directories between the root and this one, for loop detection when
+    /// following symlinks.
+    ancestors: Option<Arc<Ancestor>>,
+    /// Device of the parent directory, `None` for a root.
+    dev: Option<u64>,
+    /// Reached through a followed directory symlink, so every file below
+    /// is listed under a link's path rather than one of its hardlinks.
+    via_symlink: bool,
+}
+
+struct Ancestor {
+    key: FileKey,
+    parent: Option<Arc<Ancestor>>,
+}
+
+impl Ancestor {
+    fn contains(self: &Arc<Self>, key: FileKey) -> bool {
+        let mut current = Some(self);
+        while let Some(ancestor) = current {
+            if ancestor.key == key {
+                return true;
+            }
+            current = ancestor.parent.as_ref();
+        }
+        false
+    }
+}
+
+impl DirTask {
+    fn root(ctx: &WalkContext, root: usize) -> Self {
+        Self {
+            root,
+            dir: ctx.roots[root].0.clone(),
+            ignores: ctx.filters[root].ignores.clone(),
+            ancestors: None,
+            dev: None,
+            via_symlink: false,
+        }
+    }
+
+    fn child(&self, dir: PathBuf) -> Self {
+        Self {
+            root: self.root,
+            dir,
+            ignores: self.ignores.clone(),
+            ancestors: self.ancestors.clone(),
+            dev: self.dev,
+            via_symlink: self.via_symlink,
+        }
+    }
+}
+
+#[cfg(unix)]
+fn dir_key(_path: &Path, metadata: &fs::Metadata) -> Option<FileKey> {
+    Some(FileKey {
+        dev: metadata.dev(),
+        inode: metadata.ino(),
+    })
+}
+
+/// Volume serial and file index of a directory, which can only be opened
+/// with backup semantics.
+#[cfg(windows)]
+fn dir_key(path: &Path, _metadata: &fs::Metadata) -> Option<FileKey> {
+    let dir = fs::OpenOptions::new()
+        .read(true)
+        .custom_flags(FILE_FLAG_BACKUP_SEMANTICS)
+        .open(path)
+        .ok()?;
+    let mut info: BY_HANDLE_FILE_INFORMATION = unsafe { std::mem::zeroed() };
+    let success = unsafe { GetFileInformationByHandle(dir.as_raw_handle() as _, &mut info) };
+    (success != 0).then(|| FileKey {
+        dev: info.dwVolumeSerialNumber as u64,
+        inode: ((info.nFileIndexHigh as u64) << 32) | (info.nFileIndexLow as u64),
+    })
+}
+
+/// Scans one directory, spawning a task per subdirectory so idle threads can
+/// steal work from busy subtrees.
+fn walk_dir<'s>(
+    scope: &rayon::Scope<'s>,
+    ctx: &'s WalkContext<'s>,
+    mut task: DirTask,
+    tx: Sender<DirScan>,
+) {
+    if ctx.cancel.is_cancelled() {
+        return;
+    }
+    let metadata = match fs::metadata(&task.dir) {
+        Ok(metadata) => metadata,
+        Err(e) => {
+            let _ = tx.send(DirScan::failed(task.root, task.dir, e));
+            return;
+        }
+    };
+    let mut mounted = None;
+    if let Some(key) = dir_key(&task.dir, &metadata) {
+        if task.dev.is_some_and(|dev| dev != key.dev) {
+            mounted = Some(key.dev);
+            if ctx.one_file_system {
+                let _ = tx.send(DirScan {
+                    mounted,
+                    ..DirScan::empty(task.root, task.dir)
+                });
+                return;
+            }
+        }
+        task.dev = Some(key.dev);
+        if task.ancestors.as_ref().is_some_and(|a| a.contains(key)) {
+            let _ = tx.send(DirScan::failed(
+                task.root,
+                task.dir,
+                "symlink loop, directory already visited higher up",
+            ));
+            return;
+        }
+        task.ancestors = Some(Arc::new(Ancestor {
+            key,
+            parent: task.ancestors.take(),
+        }));
+    }
+    let modified = metadata.modified().ok();
+
+    if let Some(record) = ctx.cache.unchanged_dir(task.root, &task.dir, modified) {
+        let mut issues = Vec::new();
+        let mut layered = task.child(task.dir.clone());
+        if record.files.iter().any(|f| f == IGNORE_FILE) {
+            layered.ignores = push_ignore_file(&task.ignores, &task.dir, &mut issues);
+        }
+
+        if let Some(mut files) = replay_dir(ctx, &layered, record) {
+            for name in &record.subdirs {
+                let path = layered.dir.join(name);
+                if !layered.ignores.is_ignored(&path, true) {
+                    let child = layered.child(path);
+                    let tx = tx.clone();
+                    scope.spawn(move |scope| walk_dir(scope, ctx, child, tx));
+                }
+            }
+            for name in &record.symlinks {
+                let path = layered.dir.join(name);
+                scan_symlink(scope, ctx, &layered, path, &mut files, &mut issues, &tx);
+            }
+            let _ = tx.send(DirScan {
+                root: task.root,
+                dir: task.dir,
+                files,
+                issues,
+                listing: None,
+                mounted,
+            });
+            return;
+        }
+    }
+
+    let scan = read_dir_files(scope, ctx, task, modified, &tx);
+    let _ = tx.send(DirScan { mounted, ..scan });
+}
+
+/// Layers the directory's `.ratatidyignore` on top of `ignores`. A file that
+/// fails to parse is reported and the parent rules stay in effect.
+fn push_ignore_file(
+    ignores: &Arc<IgnoreStack>,
+    dir: &Path,
+    issues: &mut Vec<ScanIssue>,
+) -> Arc<IgnoreStack> {
+    match IgnoreStack::push(ignores, dir) {
+        Ok(stack) => stack,
+        Err(e) => {
+            issues.push(ScanIssue::new(&dir.join(IGNORE_FILE), e));
+            ignores.clone()
+        }
+    }
+}
+
+fn keeps_file(ctx: &WalkContext, task: &DirTask, path: &Path) -> bool {
+    ctx.filters[task.root].keeps_file(path) && !task.ignores.is_ignored(path, false)
+}
+
+/// Rebuilds a directory's files from its cached listing without reading the
+/// directory. Each kept file is still `lstat`-ed, as a hardlink added
+/// elsewhere changes the file's ctime but not its directory, and the cached
+/// entry is only reused while its [`ChangeStamp`] matches. Returns `None` if a
+/// listed file is gone or no longer a regular file, in which case the
+/// directory is read again. Symlinks are not cached and are resolved again by
+/// the caller.
+fn replay_dir(ctx: &WalkContext, task: &DirTask, record: &DirRecord) -> Option<Vec<ScannedFile>> {
+    record
+        .files
+        .iter()
+        .map(|name| task.dir.join(name))
+        .filter(|path| keeps_file(ctx, task, path))
+        .map(|path| {
+            let metadata = fs::symlink_metadata(&path).ok().filter(|m| m.is_file())?;
+            let mut file = scan_file(ctx, task.root, path, &metadata).ok()?;
+            if file.origin == CacheUse::Revalidated {
+                file.origin = CacheUse::Reused;
+            }
+            file.followed = task.via_symlink;
+            Some(file)
+        })
+        .collect()
+}
+
+/// Reads and stats every entry of a directory. Entries that fail are
+/// recorded as issues and skipped; the listing is only cached when the whole
+/// directory was read cleanly, so failures are retried on the next scan.
+/// The cached listing holds every child, filtered or not, so changing the
+/// filters never requires a directory to be read again.
+fn read_dir_files<'s>(
+    scope: &rayon::Scope<'s>,
+    ctx: &'s WalkContext<'s>,
+    mut task: DirTask,
+    modified: Option<SystemTime>,
+    tx: &Sender<DirScan>,
+) -> DirScan {
+    let read = match fs::read_dir(&task.dir) {
+        Ok(read) => read,
+        Err(e) => return DirScan::failed(task.root, task.dir, e),
+    };
+
+    let mut files = Vec::new();
+    let mut issues = Vec::new();
+    let mut record = DirRecord {
+        modified,
+        ..Default::default()
+    };
+
+    let mut entries = Vec::new();
+    for entry in read {
+        match entry {
+            Ok(entry) => entries.push(entry),
+            Err(e) => issues.push(ScanIssue::new(&task.dir, e)),
+        }
+    }
+    if entries.iter().any(|e| e.file_name() == IGNORE_FILE) {
+        task.ignores = push_ignore_file(&task.ignores, &task.dir, &mut issues);
+    }
+
+    for entry in entries {
+        let path = entry.path();
+        let file_type = match entry.file_type() {
+            Ok(file_type) => file_type,
+            Err(e) => {
+                issues.push(ScanIssue::new(&path, e));
+                continue;
+            }
+        };
+
+        if file_type.is_dir() {
+            record.subdirs.push(entry.file_name());
+            if !task.ignores.is_ignored(&path, true) {
+                let child = task.child(path);
+                let tx = tx.clone();
+                scope.spawn(move |scope| walk_dir(scope, ctx, child, tx));
+            }
+        } else if file_type.is_symlink() {
+            record.symlinks.push(entry.file_name());
+            scan_symlink(scope, ctx, &task, path, &mut files, &mut issues, tx);
+        } else if file_type.is_file() {
+            record.files.push(entry.file_name());
+            if !keeps_file(ctx, &task, &path) {
+                continue;
+            }
+            match entry
+                .metadata()
+                .map_err(anyhow::Error::from)
+                .and_then(|metadata| scan_file(ctx, task.root, path.clone(), &metadata))
+            {
+                Ok(file) => files.push(ScannedFile {
+                    followed: task.via_symlink,
+                    ..file
+                }),
+                Err(e) => issues.push(ScanIssue::new(&path, e)),
+            }
+        }
+    }
+
+    let listing = issues.is_empty().then_some(record);
+    DirScan {
+        root: task.root,
+        dir: task.dir,
+        files,
+        issues,
+        listing,
+        mounted: None,
+    }
+}
+
+/// Builds a file entry from fresh metadata, reading the key and link count
+/// again only when the cached entry is stale.
+fn scan_file(
+    ctx: &WalkContext,
+    root: usize,
+    path: PathBuf,
+    metadata: &fs::Metadata,
+) -> Result<ScannedFile> {
+    let modified = metadata.created().ok().or_else(|| metadata.modified().ok());
+    let size = metadata.len();
+    let changed = change_stamp(metadata);
+
+    let (key, nlink, origin) = match ctx.cache.entry(root, &path) {
+        Some(entry)
+            if entry.size == size && entry.modified == modified && entry.changed == changed =>
+        {
+            (entry.key, entry.nlink, CacheUse::Revalidated)
+        }
+        cached => {
+            let (key, nlink) = Scanner::get_file_info(&path, metadata)?;
+            let origin = if cached.is_some() {
+                CacheUse::Refreshed
+            } else {
+                CacheUse::Missed
+            };
+            (key, nlink, origin)
+        }
+    };
+
+    Ok(ScannedFile {
+        path,
+        key,
+        nlink,
+        size,
+        modified,
+        changed,
+        kind: NodeKind::File,
+        origin,
+        followed: false,
+    })
+}
+
+/// Applies the symlink policy to one link. Broken links are always reported,
+/// either as an issue or, under [`SymlinkPolicy::Report`], as a flagged node.
+fn scan_symlink<'s>(
+    scope: &rayon::Scope<'s>,
+    ctx: &'s WalkContext<'s>,
+    task: &DirTask,
+    path: PathBuf,
+    files: &mut Vec<ScannedFile>,
+    issues: &mut Vec<ScanIssue>,
+    tx: &Sender<DirScan>,
+) {
+    let target = fs::metadata(&path);
+    let broken_issue = |path: &Path| {
+        let dest = fs::read_link(path).unwrap_or_default();
+        ScanIssue::new(path, format!("broken symlink to {}", dest.display()))
+    };
+
+    match ctx.symlinks {
+        SymlinkPolicy::Ignore => {
+            if target.is_err() {
+                issues.push(broken_issue(&path));
+            }
+        }
+        SymlinkPolicy::Follow => match target {
+            Err(_) => issues.push(broken_issue(&path)),
+            Ok(metadata) if metadata.is_dir() => {
+                if !task.ignores.is_ignored(&path, true) {
+                    let mut child = task.child(path);
+                    child.via_symlink = true;
+                    let tx = tx.clone();
+                    scope.spawn(move |scope| walk_dir(scope, ctx, child, tx));
+                }
+            }
+            Ok(metadata) if metadata.is_file() => {
+                if keeps_file(ctx, task, &path) {
+                    match scan_file(ctx, task.root, path.clone(), &metadata) {
+                        Ok(file) => files.push(ScannedFile {
+                            followed: true,
+                            ..file
+                        }),
+                        Err(e) => issues.push(ScanIssue::new(&path, e)),
+                    }
+                }
+            }
+            Ok(_) => {}
+        },
+        SymlinkPolicy::Report => {
+            if task.ignores.is_ignored(&path, false) {
+                return;
+            }
+            let link = match fs::symlink_metadata(&path) {
+                Ok(link) => link,
+                Err(e) => {
+                    issues.push(ScanIssue::new(&path, e));
+                    return;
+                }
+            };
+            let (key, nlink) = match Scanner::get_file_info(&path, &link) {
+                Ok(info) => info,
+                Err(e) => {
+                    issues.push(ScanIssue::new(&path, e));
+                    return;
+                }
+            };
+            files.push(ScannedFile {
+                key,
+                nlink,
+                size: link.len(),
+                modified: link.modified().ok(),
+                changed: None,
+                kind: NodeKind::Symlink {
+                    target: fs::read_link(&path).unwrap_or_default(),
+                    broken: target.is_err(),
+                },
+                origin: CacheUse::Uncached,
+                followed: false,
+                path,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A fresh directory below the system temp dir, removed on drop.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir =
+                std::env::temp_dir().join(format!("ratatidy-test-{}-{}", name, std::process::id()));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    /// Walks `dl` and `media` with symlinks followed, without touching the
+    /// scan cache on disk.
+    fn scan_following(dl: &Path, media: &Path) -> Vec<FileNode> {
+        let roots = vec![(dl.to_path_buf(), true), (media.to_path_buf(), false)];
+        let filters: Vec<ScanFilter> = roots
+            .iter()
+            .map(|(root, _)| FilterRules::default().compile(root).unwrap())
+            .collect();
+        let root_paths: Vec<PathBuf> = roots.iter().map(|(root, _)| root.clone()).collect();
+        let mut cache = ScanCache::load(&root_paths);
+        let cancel = CancelToken::default();
+        let ctx = WalkContext {
+            roots: &roots,
+            filters: &filters,
+            cache: &cache,
+            cancel: &cancel,
+            symlinks: SymlinkPolicy::Follow,
+            one_file_system: false,
+        };
+        let pool = rayon::ThreadPoolBuilder::new()
+            .num_threads(2)
+            .build()
+            .unwrap();
+        let root_info = roots
+            .iter()
+            .map(|(path, is_download)| RootInfo {
+                path: path.clone(),
+                is_download: *is_download,
+                dev: None,
+                mounts: Vec::new(),
+            })
+            .collect();
+        let mut collector = Collector::new(None, root_info);
+        let (sender, _events) = mpsc::channel();
+        walk(&ctx, &pool, &mut collector, &sender);
+        collector.finish(&mut cache).0
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn followed_file_symlink_is_not_a_hardlink() {
+        let tmp = TempDir::new("file-symlink");
+        let (dl, media) = (tmp.0.join("dl"), tmp.0.join("media"));
+        fs::create_dir_all(dl.join("Movie.2010.1080p")).unwrap();
+        fs::create_dir_all(media.join("Movie (2010)")).unwrap();
+        let file = dl.join("Movie.2010.1080p/movie.mkv");
+        fs::write(&file, b"data").unwrap();
+        let link = media.join("Movie (2010)/movie.mkv");
+        std::os::unix::fs::symlink(&file, &link).unwrap();
+
+        let nodes = scan_following(&dl, &media);
+        assert_eq!(nodes.len(), 1);
+        let node = &nodes[0];
+        assert_eq!(node.paths, [file]);
+        assert_eq!(node.symlinks, [link]);
+        assert_eq!(node.nlink, 1);
+        assert!(node.has_downloads && !node.has_media);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn files_below_followed_dir_symlink_are_not_hardlinks() {
+        let tmp = TempDir::new("dir-symlink");
+        let (dl, media) = (tmp.0.join("dl"), tmp.0.join("media"));
+        fs::create_dir_all(dl.join("Movie.2010.1080p")).unwrap();
+        fs::create_dir_all(&media).unwrap();
+        let file = dl.join("Movie.2010.1080p/movie.mkv");
+        fs::write(&file, b"data").unwrap();
+        std::os::unix::fs::symlink(dl.join("Movie.2010.1080p"), media.join("Movie")).unwrap();
+
+        let nodes = scan_following(&dl, &media);
+        assert_eq!(nodes.len(), 1);
+        let node = &nodes[0];
+        assert_eq!(node.paths, [file]);
+        assert_eq!(node.symlinks, [media.join("Movie/movie.mkv")]);
+        assert_eq!(node.nlink, 1);
+        assert!(node.has_downloads && !node.has_media);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn file_only_reached_through_symlink_is_listed_where_the_link_is() {
+        let tmp = TempDir::new("outside-symlink");
+        let (dl, media, elsewhere) = (tmp.0.join("dl"), tmp.0.join("media"), tmp.0.join("nas"));
+        fs::create_dir_all(&dl).unwrap();
+        fs::create_dir_all(&media).unwrap();
+        fs::create_dir_all(&elsewhere).unwrap();
+        fs::write(elsewhere.join("movie.mkv"), b"data").unwrap();
+        std::os::unix::fs::symlink(&elsewhere, media.join("Movie")).unwrap();
+
+        let nodes = scan_following(&dl, &media);
+        assert_eq!(nodes.len(), 1);
+        assert!(nodes[0].paths.is_empty());
+        assert!(nodes[0].has_media && !nodes[0].has_downloads);
+    }
+}
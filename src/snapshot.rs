@@ -0,0 +1,332 @@
+use crate::fsid::MountTable;
+use crate::scanner::{FileKey, FileNode, NodeKind, RootInfo};
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io::{BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const SNAPSHOT_VERSION: u32 = 1;
+const MAGIC: &[u8; 8] = b"RTTDYSN\0";
+
+/// The nodes of one finished scan, kept so later scans can be compared
+/// against it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub taken: SystemTime,
+    pub roots: Vec<RootInfo>,
+    pub nodes: Vec<FileNode>,
+    /// Filesystem identity of every device in the snapshot, so keys can be
+    /// matched after device numbers change (see [`MountTable`]).
+    devices: Vec<(u64, String)>,
+}
+
+/// A snapshot on disk, listed without reading its nodes.
+#[derive(Debug, Clone)]
+pub struct SnapshotInfo {
+    pub path: PathBuf,
+    pub taken: SystemTime,
+}
+
+impl Snapshot {
+    pub fn new(nodes: Vec<FileNode>, roots: Vec<RootInfo>) -> Self {
+        Self {
+            taken: SystemTime::now(),
+            roots,
+            nodes,
+            devices: Vec::new(),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut reader = BufReader::new(fs::File::open(path)?);
+        let mut magic = [0; MAGIC.len()];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            bail!("not a ratatidy snapshot");
+        }
+        let version: u32 = bincode::deserialize_from(&mut reader)?;
+        if version != SNAPSHOT_VERSION {
+            bail!("snapshot format version {}", version);
+        }
+        let mut snapshot: Self = bincode::deserialize_from(&mut reader)?;
+        snapshot.remap_devices(&MountTable::load());
+        Ok(snapshot)
+    }
+
+    fn save(&mut self, path: &Path, mounts: &MountTable) -> Result<()> {
+        let mut devices: Vec<u64> = self.nodes.iter().map(|n| n.key.dev).collect();
+        devices.extend(self.roots.iter().filter_map(|r| r.dev));
+        devices.sort_unstable();
+        devices.dedup();
+        self.devices = devices
+            .into_iter()
+            .filter_map(|dev| Some((dev, mounts.identity(dev)?.to_string())))
+            .collect();
+
+        let tmp_path = path.with_extension("tmp");
+        let mut writer = BufWriter::new(fs::File::create(&tmp_path)?);
+        writer.write_all(MAGIC)?;
+        bincode::serialize_into(&mut writer, &SNAPSHOT_VERSION)?;
+        bincode::serialize_into(&mut writer, self)?;
+        writer.flush()?;
+        drop(writer);
+        fs::rename(tmp_path, path)?;
+        Ok(())
+    }
+
+    /// Rewrites device numbers to the ones their filesystems have now.
+    /// Devices that can't be identified, or aren't mounted, keep their number.
+    fn remap_devices(&mut self, mounts: &MountTable) {
+        let moved: HashMap<u64, u64> = self
+            .devices
+            .iter()
+            .filter_map(|(dev, identity)| Some((*dev, mounts.device(identity)?)))
+            .filter(|(old, new)| old != new)
+            .collect();
+        if moved.is_empty() {
+            return;
+        }
+        let remap = |key: &mut FileKey| {
+            if let Some(dev) = moved.get(&key.dev) {
+                key.dev = *dev;
+            }
+        };
+        for node in &mut self.nodes {
+            remap(&mut node.key);
+            node.duplicates.iter_mut().for_each(remap);
+        }
+        for root in &mut self.roots {
+            if let Some(dev) = root.dev.and_then(|dev| moved.get(&dev)) {
+                root.dev = Some(*dev);
+            }
+        }
+    }
+
+    /// Whether both snapshots hold the same files with the same paths, sizes
+    /// and link counts.
+    fn same_files(&self, other: &Snapshot) -> bool {
+        let files = |snapshot: &Snapshot| {
+            let mut files: Vec<_> = snapshot
+                .nodes
+                .iter()
+                .map(|n| (n.key.dev, n.key.inode, n.size, n.nlink, n.paths.clone()))
+                .collect();
+            files.sort_unstable();
+            files
+        };
+        self.nodes.len() == other.nodes.len() && files(self) == files(other)
+    }
+}
+
+pub fn snapshot_dir() -> Option<PathBuf> {
+    directories::ProjectDirs::from("com", "ratatidy", "ratatidy")
+        .map(|dirs| dirs.data_dir().join("snapshots"))
+}
+
+/// Every saved snapshot, oldest first. Snapshots are named after the
+/// millisecond they were taken, as `{secs}.{millis}.bin`.
+pub fn list() -> Vec<SnapshotInfo> {
+    let Some(entries) = snapshot_dir().and_then(|dir| fs::read_dir(dir).ok()) else {
+        return Vec::new();
+    };
+    let mut snapshots: Vec<SnapshotInfo> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let path = entry.path();
+            if path.extension()? != "bin" {
+                return None;
+            }
+            let stem = path.file_stem()?.to_str()?;
+            let (secs, millis) = stem.split_once('.')?;
+            let taken = Duration::from_secs(secs.parse().ok()?)
+                + Duration::from_millis(millis.parse().ok()?);
+            Some(SnapshotInfo {
+                taken: UNIX_EPOCH + taken,
+                path,
+            })
+        })
+        .collect();
+    snapshots.sort_by_key(|s| s.taken);
+    snapshots
+}
+
+/// Saves `snapshot` unless nothing changed since the latest one, then drops
+/// the oldest snapshots beyond `keep`. Returns whether it was saved.
+pub fn record(mut snapshot: Snapshot, keep: usize) -> Result<bool> {
+    let dir = snapshot_dir().context("no data directory available")?;
+    let existing = list();
+    if let Some(latest) = existing.last()
+        && Snapshot::load(&latest.path).is_ok_and(|latest| latest.same_files(&snapshot))
+    {
+        return Ok(false);
+    }
+
+    fs::create_dir_all(&dir)?;
+    // Scans finishing within the same millisecond take the next free one.
+    let mut millis = snapshot
+        .taken
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64;
+    let path = loop {
+        let path = dir.join(format!("{}.{:03}.bin", millis / 1000, millis % 1000));
+        if !path.exists() {
+            break path;
+        }
+        millis += 1;
+    };
+    snapshot.save(&path, &MountTable::load())?;
+
+    let total = list();
+    for old in &total[..total.len().saturating_sub(keep)] {
+        let _ = fs::remove_file(&old.path);
+    }
+    Ok(true)
+}
+
+/// A hardlink between downloads and media that no longer exists.
+#[derive(Debug, Clone)]
+pub struct BrokenLink {
+    /// Paths that still share the original file.
+    pub kept: Vec<PathBuf>,
+    /// Paths that were deleted or now hold a separate copy.
+    pub lost: Vec<PathBuf>,
+}
+
+#[derive(Debug, Clone)]
+pub struct RootGrowth {
+    pub root: PathBuf,
+    pub before: u64,
+    pub after: u64,
+}
+
+/// What happened between two snapshots.
+#[derive(Debug, Clone, Default)]
+pub struct SnapshotDiff {
+    /// Files that are only in downloads or only in media now, but weren't
+    /// before (new, or their counterpart went away).
+    pub new_orphans: Vec<PathBuf>,
+    pub broken_links: Vec<BrokenLink>,
+    /// The same file under a new path: (old path, new path).
+    pub moved: Vec<(PathBuf, PathBuf)>,
+    pub growth: Vec<RootGrowth>,
+}
+
+impl SnapshotDiff {
+    pub fn is_empty(&self) -> bool {
+        self.new_orphans.is_empty()
+            && self.broken_links.is_empty()
+            && self.moved.is_empty()
+            && self.growth.iter().all(|g| g.before == g.after)
+    }
+}
+
+fn is_linked(node: &FileNode) -> bool {
+    node.has_downloads && node.has_media
+}
+
+pub fn diff(old: &Snapshot, new: &Snapshot) -> SnapshotDiff {
+    let files = |snapshot: &Snapshot| -> HashMap<FileKey, usize> {
+        snapshot
+            .nodes
+            .iter()
+            .enumerate()
+            .filter(|(_, n)| n.kind == NodeKind::File)
+            .map(|(i, n)| (n.key, i))
+            .collect()
+    };
+    let old_files = files(old);
+    let new_files = files(new);
+    let new_keys: HashMap<&Path, FileKey> = new
+        .nodes
+        .iter()
+        .flat_map(|n| n.paths.iter().map(move |p| (p.as_path(), n.key)))
+        .collect();
+
+    let mut diff = SnapshotDiff::default();
+    for (key, &i) in &new_files {
+        let node = &new.nodes[i];
+        let before = old_files.get(key).map(|&j| &old.nodes[j]);
+        if !is_linked(node)
+            && !node.incomplete
+            && before.is_none_or(is_linked)
+            && let Some(path) = node.all_paths().next()
+        {
+            diff.new_orphans.push(path.clone());
+        }
+
+        if let Some(before) = before {
+            let removed: Vec<&PathBuf> = before
+                .paths
+                .iter()
+                .filter(|p| !node.paths.contains(p))
+                .collect();
+            let added = node.paths.iter().filter(|p| !before.paths.contains(p));
+            if is_linked(before) && !is_linked(node) {
+                diff.broken_links.push(BrokenLink {
+                    kept: node.paths.clone(),
+                    lost: removed.into_iter().cloned().collect(),
+                });
+            } else {
+                diff.moved.extend(
+                    removed
+                        .into_iter()
+                        .zip(added)
+                        .map(|(from, to)| (from.clone(), to.clone())),
+                );
+            }
+        }
+    }
+
+    // Linked files whose inode is gone while some of their paths still
+    // exist: those paths were replaced by copies.
+    for (key, &j) in &old_files {
+        let before = &old.nodes[j];
+        if is_linked(before) && !new_files.contains_key(key) {
+            // Paths still sharing an inode with the first surviving one
+            // count as kept, the rest became copies or were deleted.
+            let Some(survivor) = before.paths.iter().find_map(|p| new_keys.get(p.as_path())) else {
+                continue;
+            };
+            let (kept, lost) = before
+                .paths
+                .iter()
+                .cloned()
+                .partition(|p| new_keys.get(p.as_path()) == Some(survivor));
+            diff.broken_links.push(BrokenLink { kept, lost });
+        }
+    }
+
+    let mut roots: Vec<&PathBuf> = old
+        .roots
+        .iter()
+        .chain(&new.roots)
+        .map(|r| &r.path)
+        .collect();
+    roots.sort();
+    roots.dedup();
+    let size_below = |snapshot: &Snapshot, root: &Path| -> u64 {
+        snapshot
+            .nodes
+            .iter()
+            .filter(|n| n.kind == NodeKind::File && n.paths.iter().any(|p| p.starts_with(root)))
+            .map(|n| n.size)
+            .sum()
+    };
+    diff.growth = roots
+        .into_iter()
+        .map(|root| RootGrowth {
+            root: root.clone(),
+            before: size_below(old, root),
+            after: size_below(new, root),
+        })
+        .collect();
+
+    diff.new_orphans.sort();
+    diff.broken_links.sort_by(|a, b| a.kept.cmp(&b.kept));
+    diff.moved.sort();
+    diff
+}
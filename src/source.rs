@@ -0,0 +1,128 @@
+use crate::scanner::{CancelToken, FileNode, ScanEvent, ScanReport, Scanner};
+use anyhow::{Context, Result, bail};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::Sender;
+use std::time::SystemTime;
+
+const EXPORT_VERSION: u32 = 1;
+
+/// Where the node list comes from.
+pub trait FileSource {
+    /// Starts loading nodes on a background thread. Progress and the result
+    /// arrive on `sender` as [`ScanEvent`]s; the token aborts the load.
+    fn load(&self, sender: Sender<ScanEvent>) -> CancelToken;
+
+    /// Whether the node paths exist on this machine. Files from other
+    /// sources can't be deleted, relinked or watched from here.
+    fn is_local(&self) -> bool;
+
+    /// Short description for the dashboard.
+    fn describe(&self) -> String;
+}
+
+impl FileSource for Scanner {
+    fn load(&self, sender: Sender<ScanEvent>) -> CancelToken {
+        self.scan_async(sender)
+    }
+
+    fn is_local(&self) -> bool {
+        true
+    }
+
+    fn describe(&self) -> String {
+        "local scan".to_string()
+    }
+}
+
+/// A finished scan written by `ratatidy scan --export`, usually on the
+/// server that holds the files.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ExportedScan {
+    pub version: u32,
+    pub taken: SystemTime,
+    pub nodes: Vec<FileNode>,
+    pub report: ScanReport,
+}
+
+impl ExportedScan {
+    pub fn new(nodes: Vec<FileNode>, report: ScanReport) -> Self {
+        Self {
+            version: EXPORT_VERSION,
+            taken: SystemTime::now(),
+            nodes,
+            report,
+        }
+    }
+
+    pub fn read(path: &Path) -> Result<Self> {
+        let file = fs::File::open(path).with_context(|| path.display().to_string())?;
+        let scan: Self = serde_json::from_reader(BufReader::new(file))
+            .with_context(|| format!("{} is not a ratatidy export", path.display()))?;
+        if scan.version != EXPORT_VERSION {
+            bail!(
+                "{} uses export format version {}, expected {}",
+                path.display(),
+                scan.version,
+                EXPORT_VERSION
+            );
+        }
+        Ok(scan)
+    }
+
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let tmp_path = path.with_extension("tmp");
+        let mut writer = BufWriter::new(fs::File::create(&tmp_path)?);
+        serde_json::to_writer(&mut writer, self)?;
+        writer.flush()?;
+        drop(writer);
+        fs::rename(tmp_path, path)?;
+        Ok(())
+    }
+
+    /// The download roots and the media roots the scan covered.
+    pub fn roots(&self) -> (Vec<PathBuf>, Vec<PathBuf>) {
+        let (downloads, media) = self.report.roots.iter().partition(|r| r.is_download);
+        let paths = |roots: Vec<&crate::scanner::RootInfo>| {
+            roots.into_iter().map(|r| r.path.clone()).collect()
+        };
+        (paths(downloads), paths(media))
+    }
+}
+
+/// Nodes read from an exported scan instead of walking local folders. The
+/// file is read again on every rescan, so a scheduled export on the server
+/// keeps it current.
+pub struct SnapshotFile {
+    path: PathBuf,
+}
+
+impl SnapshotFile {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+}
+
+impl FileSource for SnapshotFile {
+    fn load(&self, sender: Sender<ScanEvent>) -> CancelToken {
+        let path = self.path.clone();
+        std::thread::spawn(move || {
+            let event = match ExportedScan::read(&path) {
+                Ok(scan) => ScanEvent::Finished(scan.nodes, scan.report),
+                Err(e) => ScanEvent::Error(format!("{:#}", e)),
+            };
+            let _ = sender.send(event);
+        });
+        CancelToken::default()
+    }
+
+    fn is_local(&self) -> bool {
+        false
+    }
+
+    fn describe(&self) -> String {
+        format!("snapshot {}", self.path.display())
+    }
+}
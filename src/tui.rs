@@ -1,9 +1,9 @@
-use std::io::{self, Stdout};
-use ratatui::{backend::CrosstermBackend, Terminal};
 use crossterm::{
     execute,
-    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+    terminal::{EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode},
 };
+use ratatui::{Terminal, backend::CrosstermBackend};
+use std::io::{self, Stdout};
 
 pub struct Tui {
     pub terminal: Terminal<CrosstermBackend<Stdout>>,
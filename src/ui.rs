@@ -1,20 +1,32 @@
-use crate::app::{App, Tab};
+use crate::app::{App, Tab, WatchStatus};
+use crate::grouping::{Group, GroupKind};
+use crate::scanner::{NodeKind, ScanPhase};
 use ratatui::{
     Frame,
     layout::{Constraint, Direction, Layout},
     style::{Color, Style, Stylize},
-    widgets::{Block, Borders, Clear, List, ListItem, ListState, Paragraph, Tabs},
+    widgets::block::Title,
+    widgets::{Block, Borders, Clear, Gauge, List, ListItem, ListState, Paragraph, Tabs},
 };
 
+/// Filesystem warnings shown above the list before collapsing the rest.
+const MAX_BANNER_LINES: usize = 3;
+
 pub fn render(app: &mut App, frame: &mut Frame) {
     app.ensure_groups();
+    let fs_warnings = app.filesystem_warnings();
+    let banner_height = match fs_warnings.len() {
+        0 => 0,
+        n => n.min(MAX_BANNER_LINES + 1) as u16 + 2,
+    };
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .constraints([
-            Constraint::Length(3), // Header/Tabs
-            Constraint::Length(3), // Dashboard
-            Constraint::Min(0),    // Main Content + Sidebar
-            Constraint::Length(3), // Footer
+            Constraint::Length(3),             // Header/Tabs
+            Constraint::Length(3),             // Dashboard
+            Constraint::Length(banner_height), // Filesystem warnings
+            Constraint::Min(0),                // Main Content + Sidebar
+            Constraint::Length(3),             // Footer
         ])
         .split(frame.size());
 
@@ -28,7 +40,31 @@ pub fn render(app: &mut App, frame: &mut Frame) {
     let main_chunks = Layout::default()
         .direction(Direction::Horizontal)
         .constraints(main_constraints)
-        .split(chunks[2]);
+        .split(chunks[3]);
+
+    // Filesystem Banner
+    if !fs_warnings.is_empty() {
+        let mut lines: Vec<ratatui::text::Line> = fs_warnings
+            .iter()
+            .take(MAX_BANNER_LINES)
+            .map(|w| ratatui::text::Line::from(format!("⚠ {}", w)))
+            .collect();
+        if fs_warnings.len() > MAX_BANNER_LINES {
+            lines.push(ratatui::text::Line::from(format!(
+                "  ...and {} more",
+                fs_warnings.len() - MAX_BANNER_LINES
+            )));
+        }
+        let banner = Paragraph::new(lines)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title(" Cross-filesystem ")
+                    .border_style(Style::default().fg(Color::Red)),
+            )
+            .style(Style::default().fg(Color::Red));
+        frame.render_widget(banner, chunks[2]);
+    }
 
     // Tabs
     let tabs_list = app.get_tabs_list();
@@ -49,6 +85,10 @@ pub fn render(app: &mut App, frame: &mut Frame) {
                     }
                 }
                 Tab::Downloads => "Downloads".to_string(),
+                Tab::DownloadFolder(idx) => match app.config.download_dirs.get(*idx) {
+                    Some(dir) => dir.name(),
+                    None => format!("Downloads {}", idx + 1),
+                },
             };
             format!("[{}] {}", i + 1, name)
         })
@@ -66,29 +106,73 @@ pub fn render(app: &mut App, frame: &mut Frame) {
     frame.render_widget(tabs, chunks[0]);
 
     // Main Content (List)
-    let groups = app.current_groups();
-    if groups.is_empty() {
+    let rows = app.rows();
+    if rows.is_empty() {
         let empty = Paragraph::new("No groups found for this view.")
             .block(Block::default().borders(Borders::ALL).title(" List "));
         frame.render_widget(empty, main_chunks[0]);
     } else {
-        let items: Vec<ListItem> = groups
+        let items: Vec<ListItem> = rows
             .iter()
-            .map(|group| {
+            .map(|row| {
                 let mut hardlink_count = 0;
                 let mut total_size = 0;
-                for node in &group.nodes {
+                for node in row.files(&app.nodes) {
                     if node.has_downloads && node.has_media {
                         hardlink_count += 1;
                     }
                     total_size += node.size;
                 }
 
-                let status = format!("[LINK:{}/{}]", hardlink_count, group.nodes.len());
+                let status = format!("[LINK:{}/{}]", hardlink_count, row.nodes.len());
 
                 let size_str = format_size(total_size);
+                let downloading = if row.files(&app.nodes).any(|n| n.incomplete) {
+                    " ⇣"
+                } else {
+                    ""
+                };
+                let marker = match (row.is_dir, row.expanded) {
+                    (false, _) => "-",
+                    (true, false) => "▸",
+                    (true, true) => "▾",
+                };
 
-                ListItem::new(format!("{:>10} {} - {}", size_str, status, group.title))
+                // Folders and files inside a group line up under its title
+                if row.depth > 0 {
+                    let name = row.path.file_name().unwrap_or_default().to_string_lossy();
+                    return ListItem::new(format!(
+                        "{:>10} {} {:13}{}{} {}{}",
+                        size_str,
+                        status,
+                        "",
+                        "  ".repeat(row.depth),
+                        marker,
+                        name,
+                        downloading
+                    ));
+                }
+
+                let group = row.group;
+                let release = &group.release;
+                let mut spans = vec![
+                    format!(
+                        "{:>10} {} {:<6} {:>5} {} {}{}",
+                        size_str,
+                        status,
+                        kind_label(group.kind),
+                        release.resolution.unwrap_or_default(),
+                        marker,
+                        display_title(group),
+                        downloading
+                    )
+                    .into(),
+                ];
+                let tags = release.tags();
+                if !tags.is_empty() {
+                    spans.push(format!("  {}", tags.join(" · ")).dim());
+                }
+                ListItem::new(ratatui::text::Line::from(spans))
             })
             .collect();
 
@@ -102,54 +186,173 @@ pub fn render(app: &mut App, frame: &mut Frame) {
         frame.render_stateful_widget(list, main_chunks[0], &mut list_state);
 
         // Sidebar / Details (Only if enabled)
-        if app.show_details {
-            if let Some(group) = groups.get(app.selected_index) {
-                let mut lines = vec![
-                    ratatui::text::Line::from(format!("Group: {}", group.title))
-                        .bold()
-                        .yellow(),
-                    ratatui::text::Line::from("-".repeat(group.title.len() + 7)).dim(),
-                ];
+        if app.show_details
+            && let Some(row) = rows.get(app.selected_index)
+        {
+            let group = row.group;
+            let mut lines = vec![
+                ratatui::text::Line::from(format!("Group: {}", group.title))
+                    .bold()
+                    .yellow(),
+                ratatui::text::Line::from("-".repeat(group.title.len() + 7)).dim(),
+                ratatui::text::Line::from(format!("Kind: {}", kind_label(group.kind))),
+            ];
+            if row.depth > 0 {
+                lines.push(ratatui::text::Line::from(format!(
+                    "Selected: {}",
+                    row.path.display()
+                )));
+            }
+            let release = &group.release;
+            let details: Vec<String> = release
+                .year
+                .map(|y| y.to_string())
+                .into_iter()
+                .chain(release.episode_label())
+                .chain(release.resolution.map(str::to_string))
+                .chain(release.tags().into_iter().map(str::to_string))
+                .collect();
+            if !details.is_empty() {
+                lines.push(ratatui::text::Line::from(format!(
+                    "Release: {}",
+                    details.join(" · ")
+                )));
+            }
 
-                for node in &group.nodes {
-                    let status = if node.has_downloads && node.has_media {
-                        " (LINKED) ".fg(Color::Green)
-                    } else if node.has_downloads {
-                        " (ORPHAN-D) ".fg(Color::Red)
-                    } else {
-                        " (ORPHAN-M) ".fg(Color::Magenta)
-                    };
+            let related = app.related_groups(group);
+            if !related.is_empty() {
+                let side = if group.media_container.is_some() {
+                    "Downloads"
+                } else {
+                    "Media"
+                };
+                lines.push(ratatui::text::Line::from(format!(
+                    "Linked in {} (g):",
+                    side
+                )));
+                for other in related {
+                    let path = other
+                        .container()
+                        .map(|p| p.display().to_string())
+                        .unwrap_or_default();
+                    lines.push(
+                        ratatui::text::Line::from(format!("  {} ({})", display_title(other), path))
+                            .green(),
+                    );
+                }
+                lines.push(ratatui::text::Line::from(""));
+            }
 
-                    let date_str = node
-                        .modified
-                        .map(|m| {
-                            let datetime: chrono::DateTime<chrono::Local> = m.into();
-                            datetime.format("%Y-%m-%d %H:%M:%S").to_string()
-                        })
-                        .unwrap_or_else(|| "Unknown date".to_string());
-
-                    lines.push(ratatui::text::Line::from(vec![
-                        "• ".into(),
-                        format_size(node.size).into(),
-                        status,
-                        format!(" ({})", date_str).dim(),
-                    ]));
+            for node in row.files(&app.nodes) {
+                let status = if let NodeKind::Symlink { broken: true, .. } = node.kind {
+                    " (BROKEN SYMLINK) ".fg(Color::Red).bold()
+                } else if let NodeKind::Symlink { .. } = node.kind {
+                    " (SYMLINK) ".fg(Color::Cyan)
+                } else if node.incomplete {
+                    " (DOWNLOADING) ".fg(Color::Cyan).bold()
+                } else if node.has_downloads && node.has_media {
+                    " (LINKED) ".fg(Color::Green)
+                } else if !node.duplicates.is_empty() {
+                    " (DUPLICATE COPY) ".fg(Color::Yellow).bold()
+                } else if node.has_downloads {
+                    " (ORPHAN-D) ".fg(Color::Red)
+                } else {
+                    " (ORPHAN-M) ".fg(Color::Magenta)
+                };
 
-                    for path in &node.paths {
-                        lines
-                            .push(ratatui::text::Line::from(format!("  {}", path.display())).dim());
-                    }
-                    lines.push(ratatui::text::Line::from(""));
+                let date_str = node
+                    .modified
+                    .map(|m| {
+                        let datetime: chrono::DateTime<chrono::Local> = m.into();
+                        datetime.format("%Y-%m-%d %H:%M:%S").to_string()
+                    })
+                    .unwrap_or_else(|| "Unknown date".to_string());
+
+                let mut spans = vec!["• ".into(), format_size(node.size).into(), status];
+                let external = node.external_links();
+                if external > 0 {
+                    spans.push(
+                        format!(" (+{} EXTERNAL LINKS) ", external)
+                            .fg(Color::LightBlue)
+                            .bold(),
+                    );
                 }
+                spans.push(format!(" ({})", date_str).dim());
+                lines.push(ratatui::text::Line::from(spans));
 
-                let details = Paragraph::new(lines)
-                    .block(Block::default().borders(Borders::ALL).title(" Details "))
-                    .wrap(ratatui::widgets::Wrap { trim: false });
-                frame.render_widget(details, main_chunks[1]);
+                for path in &node.paths {
+                    lines.push(ratatui::text::Line::from(format!("  {}", path.display())).dim());
+                }
+                for path in &node.symlinks {
+                    lines.push(
+                        ratatui::text::Line::from(format!("  ~ {} (symlink)", path.display()))
+                            .cyan(),
+                    );
+                }
+                for copy in app
+                    .nodes
+                    .iter()
+                    .filter(|n| node.duplicates.contains(&n.key))
+                {
+                    for path in &copy.paths {
+                        lines.push(
+                            ratatui::text::Line::from(format!("  = {}", path.display())).yellow(),
+                        );
+                    }
+                }
+                if let NodeKind::Symlink { target, .. } = &node.kind {
+                    lines.push(
+                        ratatui::text::Line::from(format!("  -> {}", target.display())).cyan(),
+                    );
+                }
+                lines.push(ratatui::text::Line::from(""));
             }
+
+            let details = Paragraph::new(lines)
+                .block(Block::default().borders(Borders::ALL).title(" Details "))
+                .wrap(ratatui::widgets::Wrap { trim: false });
+            frame.render_widget(details, main_chunks[1]);
         }
     }
 
+    // Scan Warnings Overlay
+    if app.show_warnings {
+        let issues = app.scan_issues();
+        let area = centered_rect(70, 60, frame.size());
+        frame.render_widget(Clear, area);
+
+        let items: Vec<ListItem> = issues
+            .iter()
+            .map(|issue| {
+                ListItem::new(vec![
+                    ratatui::text::Line::from(issue.path.display().to_string()),
+                    ratatui::text::Line::from(format!("  {}", issue.reason)).fg(Color::Red),
+                ])
+            })
+            .collect();
+
+        let list = List::new(items)
+            .block(
+                Block::default()
+                    .title(format!(" SCAN WARNINGS ({}) - Esc to close ", issues.len()))
+                    .borders(Borders::ALL)
+                    .border_style(Style::default().fg(Color::Yellow).bold()),
+            )
+            .highlight_style(Style::default().bg(Color::Blue).fg(Color::White).bold())
+            .highlight_symbol(">> ");
+
+        let mut list_state = ListState::default();
+        list_state.select(Some(app.warning_index));
+        frame.render_stateful_widget(list, area, &mut list_state);
+    }
+
+    // Changes Overlay
+    if app.changes.is_some() {
+        let area = centered_rect(80, 70, frame.size());
+        frame.render_widget(Clear, area);
+        render_changes(app, frame, area);
+    }
+
     // Confirmation Overlay
     if app.show_confirmation {
         let area = centered_rect(60, 40, frame.size());
@@ -189,6 +392,36 @@ pub fn render(app: &mut App, frame: &mut Frame) {
             }
         }
 
+        let (freed, retained, incomplete) = app.delete_preview();
+        text.push(ratatui::text::Line::from(""));
+        text.push(ratatui::text::Line::from(format!(
+            "Frees {}",
+            format_size(freed)
+        )));
+        if retained > 0 {
+            text.push(
+                ratatui::text::Line::from(format!(
+                    "{} stays on disk: linked from outside the scanned folders",
+                    format_size(retained)
+                ))
+                .fg(Color::LightBlue),
+            );
+        }
+        if incomplete > 0 {
+            let line = if app.include_incomplete {
+                format!(
+                    "Includes {} still downloading (i to keep it)",
+                    format_size(incomplete)
+                )
+            } else {
+                format!(
+                    "{} still downloading is kept (i to include it)",
+                    format_size(incomplete)
+                )
+            };
+            text.push(ratatui::text::Line::from(line).fg(Color::Cyan));
+        }
+
         text.push(ratatui::text::Line::from(""));
         text.push(
             ratatui::text::Line::from("(Enter to PURGE / Esc to Cancel)")
@@ -202,28 +435,132 @@ pub fn render(app: &mut App, frame: &mut Frame) {
         frame.render_widget(paragraph, area);
     }
 
+    // Relink Overlay
+    if app.show_relink {
+        let area = centered_rect(60, 40, frame.size());
+        frame.render_widget(Clear, area);
+
+        let popup_block = Block::default()
+            .title(" RELINK DUPLICATE COPIES ")
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::Yellow).bold());
+
+        let mut text = vec![
+            ratatui::text::Line::from("Replace each copy with a hardlink to its twin:"),
+            ratatui::text::Line::from(""),
+        ];
+
+        let target_labels = [
+            (
+                crate::app::RelinkTarget::Media,
+                " [ ] Replace Media copies (keep Downloads) ",
+            ),
+            (
+                crate::app::RelinkTarget::Downloads,
+                " [ ] Replace Downloads copies (keep Media) ",
+            ),
+        ];
+
+        for (target, label) in target_labels {
+            let selected = app.relink_target == target;
+            let style = if selected {
+                Style::default().bg(Color::Yellow).fg(Color::Black).bold()
+            } else {
+                Style::default()
+            };
+            let label = if selected {
+                label.replace("[ ]", "[x]")
+            } else {
+                label.to_string()
+            };
+            text.push(ratatui::text::Line::from(label).style(style));
+        }
+
+        text.push(ratatui::text::Line::from(""));
+        text.push(
+            ratatui::text::Line::from("Content is verified before each swap.")
+                .style(Style::default().dim()),
+        );
+        text.push(
+            ratatui::text::Line::from("(Enter to RELINK / Esc to Cancel)")
+                .style(Style::default().dim()),
+        );
+
+        let paragraph = Paragraph::new(text)
+            .block(popup_block)
+            .alignment(ratatui::layout::Alignment::Center);
+
+        frame.render_widget(paragraph, area);
+    }
+
     // Scanning Overlay
     match &app.state {
-        crate::app::AppState::Scanning { processed, .. } => {
-            let area = centered_rect(50, 20, frame.size());
+        crate::app::AppState::Scanning {
+            progress, started, ..
+        } => {
+            let area = centered_rect(60, 30, frame.size());
             frame.render_widget(Clear, area);
             let block = Block::default()
                 .title(" SCANNING ")
                 .borders(Borders::ALL)
                 .border_style(Style::default().fg(Color::Cyan).bold());
+            let inner = block.inner(area);
+            frame.render_widget(block, area);
+
+            let rows = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([Constraint::Min(0), Constraint::Length(1)])
+                .split(inner);
+
             let text = vec![
-                ratatui::text::Line::from("").into(),
-                ratatui::text::Line::from(format!("Processed {} files...", processed))
-                    .alignment(ratatui::layout::Alignment::Center)
-                    .into(),
-                ratatui::text::Line::from("").into(),
-                ratatui::text::Line::from("Please wait...")
-                    .alignment(ratatui::layout::Alignment::Center)
-                    .dim()
-                    .into(),
+                ratatui::text::Line::from(format!("Root: {}", progress.root.display())),
+                ratatui::text::Line::from(format!("In:   {}", progress.current_dir.display()))
+                    .dim(),
+                ratatui::text::Line::from(""),
+                ratatui::text::Line::from(format!(
+                    "{} files, {} seen",
+                    progress.files,
+                    format_size(progress.bytes)
+                )),
+                ratatui::text::Line::from(""),
+                ratatui::text::Line::from("(Esc to cancel / r to restart)").dim(),
             ];
-            let paragraph = Paragraph::new(text).block(block);
-            frame.render_widget(paragraph, area);
+            let paragraph = Paragraph::new(text).wrap(ratatui::widgets::Wrap { trim: false });
+            frame.render_widget(paragraph, rows[0]);
+
+            let (ratio, label) = match (progress.phase, progress.estimated_files) {
+                (ScanPhase::Hashing { done, total }, _) => (
+                    done as f64 / total.max(1) as f64,
+                    format!("Comparing possible copies: {} / {} files", done, total),
+                ),
+                (ScanPhase::Walking, Some(total)) if total > 0 => {
+                    let ratio = (progress.files as f64 / total as f64).min(1.0);
+                    let eta = if progress.files > 0 && progress.files < total {
+                        let elapsed = started.elapsed().as_secs_f64();
+                        let remaining =
+                            elapsed * (total - progress.files) as f64 / progress.files as f64;
+                        format!("ETA {}", format_duration(remaining as u64))
+                    } else {
+                        "finishing...".to_string()
+                    };
+                    (
+                        ratio,
+                        format!("{} / ~{} files | {}", progress.files, total, eta),
+                    )
+                }
+                _ => (
+                    0.0,
+                    format!(
+                        "{} files | no previous scan to estimate from",
+                        progress.files
+                    ),
+                ),
+            };
+            let gauge = Gauge::default()
+                .gauge_style(Style::default().fg(Color::Cyan).bg(Color::DarkGray))
+                .ratio(ratio)
+                .label(label);
+            frame.render_widget(gauge, rows[1]);
         }
         crate::app::AppState::Error(e) => {
             let area = centered_rect(60, 30, frame.size());
@@ -233,15 +570,11 @@ pub fn render(app: &mut App, frame: &mut Frame) {
                 .borders(Borders::ALL)
                 .border_style(Style::default().fg(Color::Red).bold());
             let text = vec![
-                ratatui::text::Line::from("Scan failed:").into(),
-                ratatui::text::Line::from("").into(),
-                ratatui::text::Line::from(e.to_string())
-                    .fg(Color::Red)
-                    .into(),
-                ratatui::text::Line::from("").into(),
-                ratatui::text::Line::from("Press 'q' to quit or 'r' to try again.")
-                    .dim()
-                    .into(),
+                ratatui::text::Line::from("Scan failed:"),
+                ratatui::text::Line::from(""),
+                ratatui::text::Line::from(e.to_string()).fg(Color::Red),
+                ratatui::text::Line::from(""),
+                ratatui::text::Line::from("Press 'q' to quit or 'r' to try again.").dim(),
             ];
             let paragraph = Paragraph::new(text)
                 .block(block)
@@ -254,6 +587,8 @@ pub fn render(app: &mut App, frame: &mut Frame) {
     // Footer
     let footer_text = if app.search_active {
         format!(" SEARCH: {}█ (Esc to cancel)", app.search_query)
+    } else if let Some(message) = &app.status_message {
+        format!(" {} ", message)
     } else {
         let sort_order_label = match app.sort_order {
             crate::app::SortOrder::Ascending => "Asc",
@@ -264,8 +599,8 @@ pub fn render(app: &mut App, frame: &mut Frame) {
             crate::app::SortOrder::Descending => "↓",
         };
         format!(
-            " Tab | i:Info | d:Delete | s:Sort ({:?}) | S:{} {} | f:Filter ({:?}) | /:Search | q:Quit ",
-            app.sort_by, sort_order_label, sort_order_arrow, app.filter
+            " Tab | Space:Expand | g:Linked | i:Info | w:Warnings | c:Changes | d:Delete | l:Relink | s:Sort ({:?}) | S:{} {} | f:Filter ({:?}) | K:Kind ({:?}) | /:Search | q:Quit ",
+            app.sort_by, sort_order_label, sort_order_arrow, app.filter, app.kind_filter
         )
     };
 
@@ -276,12 +611,92 @@ pub fn render(app: &mut App, frame: &mut Frame) {
         } else {
             Style::default()
         });
-    frame.render_widget(footer, chunks[3]);
+    frame.render_widget(footer, chunks[4]);
 
     // Dashboard
     render_dashboard(app, frame, chunks[1]);
 }
 
+fn render_changes(app: &mut App, frame: &mut Frame, area: ratatui::layout::Rect) {
+    use ratatui::text::Line;
+    let Some(view) = app.changes.as_mut() else {
+        return;
+    };
+    let taken = |i: usize| {
+        let datetime: chrono::DateTime<chrono::Local> = view.snapshots[i].taken.into();
+        datetime.format("%Y-%m-%d %H:%M").to_string()
+    };
+    let title = format!(
+        " CHANGES {} → {} - ←/→ base, Esc to close ",
+        taken(view.base),
+        taken(view.snapshots.len() - 1)
+    );
+
+    let mut lines: Vec<Line> = Vec::new();
+    match &view.diff {
+        Err(e) => lines.push(Line::from(format!("Snapshot unreadable: {}", e)).fg(Color::Red)),
+        Ok(diff) if diff.is_empty() => lines.push(Line::from("Nothing changed.")),
+        Ok(diff) => {
+            lines.push(Line::from("Space per root").bold());
+            for growth in &diff.growth {
+                let delta = if growth.after >= growth.before {
+                    format!("+{}", format_size(growth.after - growth.before))
+                } else {
+                    format!("-{}", format_size(growth.before - growth.after))
+                };
+                let line = Line::from(format!(
+                    "  {}: {} → {} ({})",
+                    growth.root.display(),
+                    format_size(growth.before),
+                    format_size(growth.after),
+                    delta
+                ));
+                lines.push(if growth.after > growth.before {
+                    line.fg(Color::Yellow)
+                } else {
+                    line
+                });
+            }
+
+            lines.push(Line::from(""));
+            lines.push(Line::from(format!("Broken links ({})", diff.broken_links.len())).bold());
+            for link in &diff.broken_links {
+                for path in &link.kept {
+                    lines.push(Line::from(format!("  {}", path.display())));
+                }
+                for path in &link.lost {
+                    lines.push(Line::from(format!("    ✗ {}", path.display())).fg(Color::Red));
+                }
+            }
+
+            lines.push(Line::from(""));
+            lines.push(Line::from(format!("New orphans ({})", diff.new_orphans.len())).bold());
+            for path in &diff.new_orphans {
+                lines.push(Line::from(format!("  {}", path.display())).fg(Color::Yellow));
+            }
+
+            lines.push(Line::from(""));
+            lines.push(Line::from(format!("Moved ({})", diff.moved.len())).bold());
+            for (from, to) in &diff.moved {
+                lines.push(Line::from(format!("  {}", from.display())).dim());
+                lines.push(Line::from(format!("    → {}", to.display())).cyan());
+            }
+        }
+    }
+
+    let visible = area.height.saturating_sub(2) as usize;
+    view.scroll = view.scroll.min(lines.len().saturating_sub(visible));
+    let paragraph = Paragraph::new(lines)
+        .block(
+            Block::default()
+                .title(title)
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(Color::Cyan).bold()),
+        )
+        .scroll((view.scroll as u16, 0));
+    frame.render_widget(paragraph, area);
+}
+
 /// helper function to create a centered rect using up certain percentage of the available rect `r`
 fn centered_rect(
     percent_x: u16,
@@ -307,7 +722,28 @@ fn centered_rect(
         .split(popup_layout[1])[1]
 }
 
-fn format_size(bytes: u64) -> String {
+/// Title with the parsed year and season, e.g. `The Bear (2022) S01`.
+fn display_title(group: &Group) -> String {
+    let mut title = group.title.clone();
+    if let Some(year) = group.release.year {
+        title.push_str(&format!(" ({})", year));
+    }
+    if let Some(label) = group.release.episode_label() {
+        title.push(' ');
+        title.push_str(&label);
+    }
+    title
+}
+
+fn kind_label(kind: GroupKind) -> &'static str {
+    match kind {
+        GroupKind::Movie => "Movie",
+        GroupKind::Series => "Series",
+        GroupKind::Other => "Other",
+    }
+}
+
+pub fn format_size(bytes: u64) -> String {
     let kb = bytes as f64 / 1024.0;
     let mb = kb / 1024.0;
     let gb = mb / 1024.0;
@@ -323,32 +759,80 @@ fn format_size(bytes: u64) -> String {
     }
 }
 
+fn format_duration(secs: u64) -> String {
+    if secs >= 3600 {
+        format!("{}h {}m", secs / 3600, (secs % 3600) / 60)
+    } else if secs >= 60 {
+        format!("{}m {}s", secs / 60, secs % 60)
+    } else {
+        format!("{}s", secs)
+    }
+}
+
 fn render_dashboard(app: &App, frame: &mut Frame, area: ratatui::layout::Rect) {
     let groups = app.current_groups();
 
     let mut total_files = 0;
     let mut total_size = 0;
     let mut saved_size = 0;
+    let mut reclaimable_size = 0;
 
     for group in groups {
-        for node in &group.nodes {
+        for node in group.files(&app.nodes) {
             total_files += 1;
             if node.has_downloads && node.has_media {
                 saved_size += node.size;
+            } else if node.external_links() == 0 && !node.incomplete {
+                reclaimable_size += node.size;
             }
             total_size += node.size;
         }
     }
 
-    let stats = format!(
-        " Files: {} | Size: {} | Saved: {} ",
+    let mut stats = format!(
+        " Files: {} | Size: {} | Saved: {} | Reclaimable: {} ",
         total_files,
         format_size(total_size),
-        format_size(saved_size)
+        format_size(saved_size),
+        format_size(reclaimable_size)
     );
+    if let Some(report) = &app.last_scan {
+        stats.push_str(&format!(
+            "| Cache: {} reused, {} revalidated, {} refreshed ",
+            report.reused, report.revalidated, report.refreshed
+        ));
+    }
+
+    let mut block = Block::default().borders(Borders::ALL).title(" Dashboard ");
+    if let Some(source) = &app.remote_source {
+        block = block.title(format!(" ◆ {} (read-only) ", source).fg(Color::Cyan).bold());
+    }
+    match &app.watch {
+        WatchStatus::Off => {}
+        WatchStatus::Live => block = block.title(" ● live ".fg(Color::Green).bold()),
+        WatchStatus::Polling { every, reason } => {
+            let every = if every.is_zero() {
+                "watching off".to_string()
+            } else {
+                format!("rescan every {}", format_duration(every.as_secs()))
+            };
+            block = block.title(format!(" ⟳ {}: {} ", every, reason).fg(Color::Red).bold());
+        }
+    }
+    let issue_count = app.scan_issues().len();
+    if issue_count > 0 {
+        block = block.title(
+            Title::from(
+                format!(" ⚠ {} skipped (w) ", issue_count)
+                    .fg(Color::Red)
+                    .bold(),
+            )
+            .alignment(ratatui::layout::Alignment::Right),
+        );
+    }
 
     let dashboard = Paragraph::new(stats)
-        .block(Block::default().borders(Borders::ALL).title(" Dashboard "))
+        .block(block)
         .style(Style::default().fg(Color::Yellow).bold())
         .alignment(ratatui::layout::Alignment::Center);
 
@@ -0,0 +1,227 @@
+use crate::filters::{FilterRules, ScanFilter};
+use crate::scanner::{FileKey, Scanner};
+use anyhow::Result;
+use notify::event::{AccessKind, AccessMode, EventKind, ModifyKind};
+use notify::{RecursiveMode, Watcher};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Quiet period before a burst of events is applied, so an import that
+/// touches a file several times is only processed once.
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Longest a continuous stream of events is held back.
+const MAX_BATCH_DELAY: Duration = Duration::from_secs(2);
+
+/// A file below one of the roots that appeared or changed, as seen by a
+/// fresh stat.
+#[derive(Debug, Clone)]
+pub struct WatchedFile {
+    pub path: PathBuf,
+    pub key: FileKey,
+    pub nlink: u32,
+    pub size: u64,
+    pub modified: Option<SystemTime>,
+}
+
+#[derive(Debug, Clone)]
+pub enum FileChange {
+    Upserted(WatchedFile),
+    /// The path, and everything below it if it was a directory, is gone.
+    Removed(PathBuf),
+    /// The path, and everything below it if it is a directory, still exists
+    /// but no longer passes the filters.
+    Excluded(PathBuf),
+}
+
+#[derive(Debug)]
+pub enum WatchEvent {
+    Changes(Vec<FileChange>),
+    /// The kernel dropped events, so only a full rescan is accurate again.
+    RescanNeeded,
+    /// Watching stopped working, e.g. the inotify watch limit was reached
+    /// while adding a new directory.
+    Unavailable(String),
+}
+
+/// Live view of the scan roots. Dropping it stops watching.
+pub struct RootWatcher {
+    pub events: Receiver<WatchEvent>,
+    _watcher: notify::RecommendedWatcher,
+}
+
+/// Starts watching every root recursively. Fails when a root can't be
+/// watched, typically because the inotify watch limit is too low for the
+/// library, so the caller can fall back to periodic rescans.
+pub fn watch(roots: Vec<PathBuf>, rules: &FilterRules) -> Result<RootWatcher> {
+    let filters = roots
+        .iter()
+        .map(|root| rules.compile(root))
+        .collect::<Result<Vec<_>>>()?;
+
+    let (raw_tx, raw_rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(move |event| {
+        let _ = raw_tx.send(event);
+    })?;
+    for root in &roots {
+        watcher
+            .watch(root, RecursiveMode::Recursive)
+            .map_err(|e| anyhow::anyhow!("{}: {}", root.display(), describe(&e)))?;
+    }
+
+    let (tx, rx) = mpsc::channel();
+    std::thread::spawn(move || process(raw_rx, &roots, &filters, &tx));
+    Ok(RootWatcher {
+        events: rx,
+        _watcher: watcher,
+    })
+}
+
+fn describe(error: &notify::Error) -> String {
+    match error.kind {
+        notify::ErrorKind::MaxFilesWatch => {
+            "inotify watch limit reached (raise fs.inotify.max_user_watches)".to_string()
+        }
+        _ => error.to_string(),
+    }
+}
+
+/// Batches raw events and turns each batch into node changes. Paths are
+/// stat-ed here rather than trusting the event kind, since a burst may hold
+/// a create, a rename and a delete of the same path.
+fn process(
+    raw: Receiver<notify::Result<notify::Event>>,
+    roots: &[PathBuf],
+    filters: &[ScanFilter],
+    tx: &Sender<WatchEvent>,
+) {
+    while let Ok(first) = raw.recv() {
+        // Path -> whether it was created, renamed or removed rather than
+        // just written to; only those make a directory worth walking.
+        let mut touched: BTreeMap<PathBuf, bool> = BTreeMap::new();
+        let mut rescan = false;
+        let started = Instant::now();
+        let mut next = Some(first);
+
+        while let Some(result) = next {
+            match result {
+                Ok(event) => {
+                    rescan |= event.need_rescan();
+                    let structural = match event.kind {
+                        EventKind::Access(AccessKind::Close(AccessMode::Write)) => Some(false),
+                        EventKind::Access(_) => None,
+                        EventKind::Modify(ModifyKind::Name(_))
+                        | EventKind::Create(_)
+                        | EventKind::Remove(_) => Some(true),
+                        _ => Some(false),
+                    };
+                    if let Some(structural) = structural {
+                        for path in event.paths {
+                            *touched.entry(path).or_default() |= structural;
+                        }
+                    }
+                }
+                Err(e) if matches!(e.kind, notify::ErrorKind::MaxFilesWatch) => {
+                    let _ = tx.send(WatchEvent::Unavailable(describe(&e)));
+                    return;
+                }
+                // Usually a directory that vanished before it could be
+                // watched; the remove event covers it.
+                Err(_) => {}
+            }
+            if started.elapsed() >= MAX_BATCH_DELAY {
+                break;
+            }
+            next = raw.recv_timeout(DEBOUNCE).ok();
+        }
+
+        let event = if rescan {
+            WatchEvent::RescanNeeded
+        } else {
+            let changes: Vec<FileChange> = touched
+                .into_iter()
+                .flat_map(|(path, structural)| changes_for(roots, filters, path, structural))
+                .collect();
+            if changes.is_empty() {
+                continue;
+            }
+            WatchEvent::Changes(changes)
+        };
+        if tx.send(event).is_err() {
+            return;
+        }
+    }
+}
+
+fn changes_for(
+    roots: &[PathBuf],
+    filters: &[ScanFilter],
+    path: PathBuf,
+    structural: bool,
+) -> Vec<FileChange> {
+    // The deepest root wins when roots are nested.
+    let Some(root) = (0..roots.len())
+        .filter(|&i| path.starts_with(&roots[i]))
+        .max_by_key(|&i| roots[i].components().count())
+    else {
+        return Vec::new();
+    };
+    let filter = &filters[root];
+
+    let metadata = match fs::symlink_metadata(&path) {
+        Ok(metadata) => metadata,
+        Err(_) => return vec![FileChange::Removed(path)],
+    };
+    let mut changes = Vec::new();
+    if metadata.is_dir() {
+        if filter.is_ignored(&path, true) {
+            changes.push(FileChange::Excluded(path));
+        } else if structural {
+            walk_new_dir(filter, &path, &mut changes);
+        }
+    } else if metadata.is_file() {
+        if filter.keeps_file(&path) && !filter.is_ignored(&path, false) {
+            changes.extend(stat_file(path, &metadata).map(FileChange::Upserted));
+        } else {
+            changes.push(FileChange::Excluded(path));
+        }
+    }
+    // Symlinks are left to the next full scan and its symlink policy.
+    changes
+}
+
+/// Lists a directory that was created or moved into a root. Anything that
+/// can't be read is left for the next rescan.
+fn walk_new_dir(filter: &ScanFilter, dir: &Path, changes: &mut Vec<FileChange>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Ok(metadata) = fs::symlink_metadata(&path) else {
+            continue;
+        };
+        if metadata.is_dir() {
+            if !filter.is_ignored(&path, true) {
+                walk_new_dir(filter, &path, changes);
+            }
+        } else if metadata.is_file() && filter.keeps_file(&path) && !filter.is_ignored(&path, false)
+        {
+            changes.extend(stat_file(path, &metadata).map(FileChange::Upserted));
+        }
+    }
+}
+
+fn stat_file(path: PathBuf, metadata: &fs::Metadata) -> Option<WatchedFile> {
+    let (key, nlink) = Scanner::get_file_info(&path, metadata).ok()?;
+    Some(WatchedFile {
+        key,
+        nlink,
+        size: metadata.len(),
+        modified: metadata.created().ok().or_else(|| metadata.modified().ok()),
+        path,
+    })
+}